@@ -0,0 +1,253 @@
+use crate::error::ServerError;
+use crate::key_file::KeyFile;
+use crate::{read_packet, write_packet};
+use brlapi_types::{AuthType, ClientPacketData, ErrorCode, ServerPacket, ServerPacketData};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use subtle::ConstantTimeEq;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::task::spawn_blocking;
+use tokio::time::sleep;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+  pub uid: u32,
+  pub gid: u32,
+}
+#[derive(Debug, Clone, Default)]
+pub struct CredentialsPolicy {
+  pub users: Vec<u32>,
+  pub groups: Vec<u32>,
+}
+impl CredentialsPolicy {
+  pub fn allows(&self, credentials: &PeerCredentials) -> bool {
+    if self.users.contains(&credentials.uid) || self.groups.contains(&credentials.gid) {
+      return true;
+    }
+    !self.groups.is_empty() && group_list(credentials).iter().any(|group| self.groups.contains(group))
+  }
+}
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)]
+fn group_list(credentials: &PeerCredentials) -> Vec<u32> {
+  let mut buffer: Vec<libc::c_char> = vec![0; 1024];
+  let mut passwd = std::mem::MaybeUninit::<libc::passwd>::uninit();
+  let mut result = std::ptr::null_mut();
+  loop {
+    let status = unsafe { libc::getpwuid_r(credentials.uid, passwd.as_mut_ptr(), buffer.as_mut_ptr(), buffer.len(), &mut result) };
+    if status == libc::ERANGE && buffer.len() < 1 << 20 {
+      buffer.resize(buffer.len() * 2, 0);
+      continue;
+    }
+    if status != 0 || result.is_null() {
+      return vec![credentials.gid];
+    }
+    break;
+  }
+  let name = unsafe { (*result).pw_name };
+  let mut count: libc::c_int = 32;
+  loop {
+    let capacity = count;
+    let mut groups = vec![0; capacity as usize];
+    if unsafe { libc::getgrouplist(name, credentials.gid as _, groups.as_mut_ptr(), &mut count) } >= 0 {
+      groups.truncate(count as usize);
+      return groups.into_iter().map(|group| group as u32).collect();
+    }
+    if capacity >= 1 << 16 {
+      return vec![credentials.gid];
+    }
+    count = count.max(capacity * 2);
+  }
+}
+#[cfg(not(unix))]
+fn group_list(credentials: &PeerCredentials) -> Vec<u32> {
+  vec![credentials.gid]
+}
+#[derive(Debug, Clone)]
+pub enum AuthKey {
+  Static(Vec<u8>),
+  File(KeyFile),
+}
+impl AuthKey {
+  pub fn current(&self) -> Result<Vec<u8>, std::io::Error> {
+    match self {
+      AuthKey::Static(key) => Ok(key.clone()),
+      AuthKey::File(key_file) => key_file.key(),
+    }
+  }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Peer {
+  Tcp(SocketAddr),
+  Local(Option<PeerCredentials>),
+}
+impl Peer {
+  pub fn is_loopback(&self) -> bool {
+    match self {
+      Peer::Tcp(address) => address.ip().is_loopback(),
+      Peer::Local(_) => true,
+    }
+  }
+  pub fn credentials(&self) -> Option<PeerCredentials> {
+    match self {
+      Peer::Tcp(_) => None,
+      Peer::Local(credentials) => *credentials,
+    }
+  }
+  fn identity(&self) -> PeerIdentity {
+    match self {
+      Peer::Tcp(address) => PeerIdentity::Address(address.ip()),
+      Peer::Local(Some(credentials)) => PeerIdentity::User(credentials.uid),
+      Peer::Local(None) => PeerIdentity::Local,
+    }
+  }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum PeerIdentity {
+  Address(IpAddr),
+  User(u32),
+  Local,
+}
+#[derive(Debug, Clone)]
+pub enum AuthMethod {
+  None { loopback_only: bool },
+  Key(AuthKey),
+  Credentials(CredentialsPolicy),
+}
+impl AuthMethod {
+  fn auth_type(&self) -> AuthType {
+    match self {
+      AuthMethod::None { loopback_only: _ } => AuthType::None,
+      AuthMethod::Key(_) => AuthType::Key,
+      AuthMethod::Credentials(_) => AuthType::Credentials,
+    }
+  }
+  fn is_available(&self, peer: &Peer) -> bool {
+    match self {
+      AuthMethod::None { loopback_only } => !loopback_only || peer.is_loopback(),
+      AuthMethod::Key(_) => true,
+      AuthMethod::Credentials(_) => peer.credentials().is_some(),
+    }
+  }
+  async fn verify(&self, peer: &Peer, key: &[u8]) -> bool {
+    match self {
+      AuthMethod::None { loopback_only: _ } => true,
+      AuthMethod::Key(auth_key) => auth_key.current().is_ok_and(|auth_key| bool::from(key.ct_eq(&auth_key))),
+      AuthMethod::Credentials(policy) => {
+        let Some(credentials) = peer.credentials() else {
+          return false;
+        };
+        let policy = policy.clone();
+        spawn_blocking(move || policy.allows(&credentials)).await.unwrap_or(false)
+      },
+    }
+  }
+}
+#[derive(Debug, Clone, Copy)]
+pub struct AuthLimits {
+  pub max_attempts: u32,
+  pub backoff: Duration,
+  pub max_backoff: Duration,
+  pub lockout_threshold: u32,
+  pub lockout_duration: Duration,
+}
+impl Default for AuthLimits {
+  fn default() -> Self {
+    AuthLimits { max_attempts: 5, backoff: Duration::from_millis(500), max_backoff: Duration::from_secs(30), lockout_threshold: 10, lockout_duration: Duration::from_secs(300) }
+  }
+}
+#[derive(Debug, Clone)]
+pub struct AuthPolicy {
+  pub methods: Vec<AuthMethod>,
+  pub limits: AuthLimits,
+}
+impl Default for AuthPolicy {
+  fn default() -> Self {
+    AuthPolicy { methods: vec![AuthMethod::None { loopback_only: false }], limits: AuthLimits::default() }
+  }
+}
+#[derive(Debug)]
+struct FailureRecord {
+  failures: u32,
+  last_failure: Instant,
+  locked_until: Option<Instant>,
+}
+#[derive(Debug, Clone, Default)]
+pub(crate) struct AuthFailures {
+  records: Arc<Mutex<HashMap<PeerIdentity, FailureRecord>>>,
+}
+impl AuthFailures {
+  fn is_locked_out(&self, peer: &Peer) -> bool {
+    let records = self.records.lock().unwrap();
+    records.get(&peer.identity()).and_then(|record| record.locked_until).is_some_and(|locked_until| Instant::now() < locked_until)
+  }
+  fn record_failure(&self, peer: &Peer, limits: &AuthLimits) -> Duration {
+    let now = Instant::now();
+    let mut records = self.records.lock().unwrap();
+    records.retain(|_, record| record.locked_until.is_some_and(|locked_until| now < locked_until) || now.duration_since(record.last_failure) < limits.lockout_duration);
+    let record = records.entry(peer.identity()).or_insert(FailureRecord { failures: 0, last_failure: now, locked_until: None });
+    record.failures += 1;
+    record.last_failure = now;
+    if record.failures >= limits.lockout_threshold {
+      record.locked_until = Some(now + limits.lockout_duration);
+    }
+    limits.backoff.saturating_mul(2u32.saturating_pow(record.failures - 1)).min(limits.max_backoff)
+  }
+  fn clear(&self, peer: &Peer) {
+    self.records.lock().unwrap().remove(&peer.identity());
+  }
+}
+pub(crate) async fn authenticate<S: AsyncRead + AsyncWrite + Unpin>(socket: &mut S, policy: &AuthPolicy, failures: &AuthFailures, peer: &Peer, packet_timeout: Duration) -> Result<bool, ServerError> {
+  let methods = policy.methods.iter().filter(|method| method.is_available(peer)).collect::<Vec<_>>();
+  if methods.iter().any(|method| method.auth_type() == AuthType::None) {
+    write_packet(ServerPacket { data: ServerPacketData::Auth { auth_types: vec![AuthType::None] }}, socket).await?;
+    return Ok(true);
+  }
+  if methods.is_empty() {
+    write_packet(ServerPacket { data: ServerPacketData::Error { code: ErrorCode::ConnectionRefused }}, socket).await?;
+    return Ok(false);
+  }
+  let mut auth_types = Vec::new();
+  for method in &methods {
+    if !auth_types.contains(&method.auth_type()) {
+      auth_types.push(method.auth_type());
+    }
+  }
+  if failures.is_locked_out(peer) {
+    write_packet(ServerPacket { data: ServerPacketData::Error { code: ErrorCode::AuthenticationFailed }}, socket).await?;
+    return Ok(false);
+  }
+  write_packet(ServerPacket { data: ServerPacketData::Auth { auth_types }}, socket).await?;
+  let mut attempts = 0;
+  loop {
+    let auth_packet = read_packet(socket, packet_timeout).await?;
+    match auth_packet.data {
+      ClientPacketData::Auth { auth_type, key } => {
+        let mut verified = false;
+        for method in methods.iter().filter(|method| method.auth_type() == auth_type) {
+          if method.verify(peer, &key).await {
+            verified = true;
+            break;
+          }
+        }
+        if verified {
+          failures.clear(peer);
+          write_packet(ServerPacket { data: ServerPacketData::Ack }, socket).await?;
+          return Ok(true);
+        }
+        attempts += 1;
+        sleep(failures.record_failure(peer, &policy.limits)).await;
+        write_packet(ServerPacket { data: ServerPacketData::Error { code: ErrorCode::AuthenticationFailed }}, socket).await?;
+        if attempts >= policy.limits.max_attempts || failures.is_locked_out(peer) {
+          return Ok(false);
+        }
+      },
+      _ => {
+        write_packet(ServerPacket { data: ServerPacketData::Error { code: ErrorCode::BadProtocolVersion }}, socket).await?;
+        return Ok(false);
+      }
+    }
+  }
+}
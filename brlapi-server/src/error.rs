@@ -0,0 +1,54 @@
+use brlapi_types::ErrorCode;
+use std::fmt;
+use tokio::sync::{mpsc, oneshot};
+
+#[derive(Debug)]
+pub enum ServerError {
+  Io(std::io::Error),
+  Protocol(binrw::Error),
+  MalformedPacket { code: ErrorCode, packet: Vec<u8> },
+  Translation(String),
+  Charset(String),
+  BackendGone,
+}
+impl fmt::Display for ServerError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ServerError::Io(error) => write!(f, "I/O error: {error}"),
+      ServerError::Protocol(error) => write!(f, "protocol error: {error}"),
+      ServerError::MalformedPacket { code, packet: _ } => write!(f, "malformed packet from client: {code:?}"),
+      ServerError::Translation(message) => write!(f, "braille translation failed: {message}"),
+      ServerError::Charset(message) => write!(f, "charset conversion failed: {message}"),
+      ServerError::BackendGone => write!(f, "the server backend has gone away"),
+    }
+  }
+}
+impl std::error::Error for ServerError {
+  fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+    match self {
+      ServerError::Io(error) => Some(error),
+      ServerError::Protocol(error) => Some(error),
+      _ => None,
+    }
+  }
+}
+impl From<std::io::Error> for ServerError {
+  fn from(error: std::io::Error) -> Self {
+    ServerError::Io(error)
+  }
+}
+impl From<binrw::Error> for ServerError {
+  fn from(error: binrw::Error) -> Self {
+    ServerError::Protocol(error)
+  }
+}
+impl<T> From<mpsc::error::SendError<T>> for ServerError {
+  fn from(_: mpsc::error::SendError<T>) -> Self {
+    ServerError::BackendGone
+  }
+}
+impl From<oneshot::error::RecvError> for ServerError {
+  fn from(_: oneshot::error::RecvError) -> Self {
+    ServerError::BackendGone
+  }
+}
@@ -0,0 +1,68 @@
+use std::fs::{self, OpenOptions};
+use std::io::{ErrorKind, Write};
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+#[derive(Debug)]
+struct CachedKey {
+  modified: SystemTime,
+  key: Vec<u8>,
+}
+#[derive(Debug, Clone)]
+pub struct KeyFile {
+  path: PathBuf,
+  cache: Arc<Mutex<Option<CachedKey>>>,
+}
+fn generate_key() -> Result<Vec<u8>, std::io::Error> {
+  let mut bytes = [0u8; 16];
+  getrandom::fill(&mut bytes)?;
+  let mut key = bytes.iter().map(|byte| format!("{byte:02x}")).collect::<String>();
+  key.push('\n');
+  Ok(key.into_bytes())
+}
+fn create_key_file(path: &Path, permissions: u32) -> Result<(), std::io::Error> {
+  let mut options = OpenOptions::new();
+  options.write(true).create_new(true);
+  #[cfg(unix)]
+  options.mode(permissions);
+  #[cfg(not(unix))]
+  let _ = permissions;
+  let mut file = match options.open(path) {
+    Ok(file) => file,
+    Err(error) if error.kind() == ErrorKind::AlreadyExists => return Ok(()),
+    Err(error) => return Err(error),
+  };
+  file.write_all(&generate_key()?)?;
+  file.sync_all()
+}
+impl KeyFile {
+  pub fn open(path: impl Into<PathBuf>) -> Result<Self, std::io::Error> {
+    Self::open_with_permissions(path, 0o640)
+  }
+  pub fn open_with_permissions(path: impl Into<PathBuf>, permissions: u32) -> Result<Self, std::io::Error> {
+    let path = path.into();
+    create_key_file(&path, permissions)?;
+    let key_file = KeyFile { path, cache: Arc::new(Mutex::new(None)) };
+    key_file.key()?;
+    Ok(key_file)
+  }
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+  pub fn key(&self) -> Result<Vec<u8>, std::io::Error> {
+    let modified = fs::metadata(&self.path)?.modified()?;
+    let mut cache = self.cache.lock().unwrap();
+    if let Some(cached) = cache.as_ref() && cached.modified == modified {
+      return Ok(cached.key.clone());
+    }
+    let key = fs::read(&self.path)?;
+    if key.is_empty() {
+      return Err(std::io::Error::new(ErrorKind::InvalidData, format!("{} is empty", self.path.display())));
+    }
+    *cache = Some(CachedKey { modified, key: key.clone() });
+    Ok(key)
+  }
+}
@@ -0,0 +1,121 @@
+use brlapi_types::ErrorCode;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct KeyRanges {
+  accepted: Vec<(u64, u64)>,
+}
+impl Default for KeyRanges {
+  fn default() -> Self {
+    KeyRanges { accepted: vec![(0, u64::MAX)] }
+  }
+}
+impl KeyRanges {
+  pub(crate) fn accept(&mut self, ranges: &[(u64, u64)]) -> Result<(), ErrorCode> {
+    check_ranges(ranges)?;
+    for &(first, last) in ranges {
+      self.remove(first, last);
+      let index = self.accepted.partition_point(|(start, _)| *start < first);
+      self.accepted.insert(index, (first, last));
+      self.merge();
+    }
+    Ok(())
+  }
+  pub(crate) fn ignore(&mut self, ranges: &[(u64, u64)]) -> Result<(), ErrorCode> {
+    check_ranges(ranges)?;
+    for &(first, last) in ranges {
+      self.remove(first, last);
+    }
+    Ok(())
+  }
+  pub(crate) fn contains(&self, key: u64) -> bool {
+    let index = self.accepted.partition_point(|(start, _)| *start <= key);
+    index > 0 && key <= self.accepted[index-1].1
+  }
+  fn remove(&mut self, first: u64, last: u64) {
+    let mut remaining = Vec::with_capacity(self.accepted.len()+1);
+    for &(start, end) in &self.accepted {
+      if end < first || start > last {
+        remaining.push((start, end));
+        continue;
+      }
+      if start < first {
+        remaining.push((start, first-1));
+      }
+      if end > last {
+        remaining.push((last+1, end));
+      }
+    }
+    self.accepted = remaining;
+  }
+  fn merge(&mut self) {
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.accepted.len());
+    for &(start, end) in &self.accepted {
+      match merged.last_mut() {
+        Some(previous) if previous.1.checked_add(1).is_none_or(|next| start <= next) => previous.1 = previous.1.max(end),
+        _ => merged.push((start, end)),
+      }
+    }
+    self.accepted = merged;
+  }
+}
+fn check_ranges(ranges: &[(u64, u64)]) -> Result<(), ErrorCode> {
+  if ranges.iter().any(|(first, last)| first > last) {
+    return Err(ErrorCode::InvalidParameter);
+  }
+  Ok(())
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn ranges(accepted: &[(u64, u64)]) -> KeyRanges {
+    KeyRanges { accepted: accepted.to_vec() }
+  }
+  #[test]
+  fn accepts_everything_by_default() {
+    let keys = KeyRanges::default();
+    assert!(keys.contains(0));
+    assert!(keys.contains(u64::MAX));
+  }
+  #[test]
+  fn ignore_splits_ranges() {
+    let mut keys = KeyRanges::default();
+    keys.ignore(&[(10, 20)]).unwrap();
+    assert_eq!(keys, ranges(&[(0, 9), (21, u64::MAX)]));
+    assert!(keys.contains(9) && !keys.contains(10) && !keys.contains(20) && keys.contains(21));
+  }
+  #[test]
+  fn ignore_at_edges() {
+    let mut keys = KeyRanges::default();
+    keys.ignore(&[(0, 0), (u64::MAX, u64::MAX)]).unwrap();
+    assert_eq!(keys, ranges(&[(1, u64::MAX-1)]));
+    keys.ignore(&[(0, u64::MAX)]).unwrap();
+    assert_eq!(keys, ranges(&[]));
+    assert!(!keys.contains(0) && !keys.contains(u64::MAX));
+  }
+  #[test]
+  fn accept_merges_adjacent_and_overlapping_ranges() {
+    let mut keys = ranges(&[]);
+    keys.accept(&[(5, 9), (20, 30)]).unwrap();
+    keys.accept(&[(10, 12)]).unwrap();
+    assert_eq!(keys, ranges(&[(5, 12), (20, 30)]));
+    keys.accept(&[(11, 25)]).unwrap();
+    assert_eq!(keys, ranges(&[(5, 30)]));
+  }
+  #[test]
+  fn accept_at_edges() {
+    let mut keys = ranges(&[]);
+    keys.accept(&[(u64::MAX, u64::MAX)]).unwrap();
+    keys.accept(&[(0, 0)]).unwrap();
+    assert_eq!(keys, ranges(&[(0, 0), (u64::MAX, u64::MAX)]));
+    keys.accept(&[(1, u64::MAX-1)]).unwrap();
+    assert_eq!(keys, KeyRanges::default());
+  }
+  #[test]
+  fn rejects_reversed_ranges() {
+    let mut keys = KeyRanges::default();
+    assert_eq!(keys.ignore(&[(1, 2), (5, 4)]), Err(ErrorCode::InvalidParameter));
+    assert_eq!(keys.accept(&[(u64::MAX, 0)]), Err(ErrorCode::InvalidParameter));
+    assert_eq!(keys, KeyRanges::default());
+  }
+}
@@ -1,19 +1,44 @@
+pub mod auth;
+pub mod error;
+pub mod key_file;
+mod key_ranges;
+mod parameters;
+pub mod translator;
+mod tty;
+pub mod unix_socket;
+use auth::{AuthFailures, AuthPolicy, Peer, PeerCredentials, authenticate};
 use binrw::{BinRead, BinWrite};
-use brlapi_types::{AuthType, ClientPacket, ClientPacketData, ErrorCode, ServerPacket, ServerPacketData};
-use brlapi_types::keycode::Keycode;
+use brlapi_types::host::BindTarget;
+use brlapi_types::{ClientPacket, ClientPacketData, ErrorCode, MAX_PACKET_SIZE, PacketType, ParameterRequestFlags, ParameterValueFlags, ServerPacket, ServerPacketData};
+use brlapi_types::keycode::{BrailleCommand, Keycode, KeycodeFlags};
+use brlapi_types::parameter::{Parameter, ParameterValue};
+use error::ServerError;
 #[cfg(feature = "platform_iconv")]
 use iconv_native::decode_lossy;
 #[cfg(feature = "libiconv")]
 use iconv_native_libiconv::decode_lossy;
-use louis::Louis;
 use ndarray::{Array1, Array2, s};
+use std::collections::HashMap;
 use std::io::Cursor;
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::thread;
 use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
-use tokio::net::{TcpListener, TcpStream};
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+#[cfg(unix)]
+use unix_socket::bind_unix_socket;
+use unix_socket::UnixSocketConfig;
 use tokio::sync::{mpsc, Mutex, oneshot};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::{Duration, sleep, timeout};
+use key_ranges::KeyRanges;
+use parameters::{ParameterStore, Scope};
+use translator::{BrailleTranslator, Translation, TranslatorFactory, braille_pattern, braille_string};
+use tty::{ClientId, TtyTree};
 
 pub struct ServerBackend {
   pub driver_name: String,
@@ -22,119 +47,742 @@ pub struct ServerBackend {
   pub lines: u8,
   pub braille_tx: mpsc::Sender<Array2<u8>>,
   pub keycode_rx: mpsc::Receiver<Keycode>,
-  pub louis_rx: mpsc::Receiver<LouisRequest>
+  pub louis_rx: mpsc::Receiver<LouisRequest>,
+  pub raw: Option<RawChannel>,
+  pub control_tx: Option<mpsc::Sender<DriverControl>>,
+  pub parameter_rx: Option<mpsc::Receiver<ParameterChange>>,
+  pub focus_rx: Option<mpsc::Receiver<u32>>
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParameterChange {
+  pub sub_parameter: u64,
+  pub value: ParameterValue
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DriverControl {
+  Suspend,
+  Resume,
+}
+pub struct RawChannel {
+  pub packet_tx: mpsc::Sender<Vec<u8>>,
+  pub packet_rx: mpsc::Receiver<Vec<u8>>
+}
+#[derive(Debug, Clone)]
+struct WrittenText {
+  text: String,
+  region: (u32, u32),
+  cursor: Option<u32>,
+  and: Option<Vec<u8>>,
+  or: Option<Vec<u8>>
+}
+struct ClientSession {
+  packet_tx: mpsc::Sender<ServerPacketData>,
+  key_ranges: KeyRanges,
+  cursor_position: Option<u16>,
+  braille_matrix: Array2<u8>,
+  text_columns: Vec<u16>,
+  written_texts: Vec<WrittenText>
+}
+impl ClientSession {
+  fn new(packet_tx: mpsc::Sender<ServerPacketData>, columns: u8, lines: u8) -> Self {
+    let cells = columns as usize * lines as usize;
+    ClientSession { packet_tx, key_ranges: KeyRanges::default(), cursor_position: None, braille_matrix: Array2::zeros((lines as usize, columns as usize)), text_columns: (0..cells as u16).collect(), written_texts: Vec::new() }
+  }
+  fn text_column(&self, column: u16) -> u16 {
+    self.text_columns.get(column as usize).copied().unwrap_or(column)
+  }
+  fn map_routing(&self, command: BrailleCommand) -> BrailleCommand {
+    match command {
+      BrailleCommand::RouteCursorToCharacter { column } => BrailleCommand::RouteCursorToCharacter { column: self.text_column(column) },
+      BrailleCommand::NewClipboardAtCharacter { column } => BrailleCommand::NewClipboardAtCharacter { column: self.text_column(column) },
+      BrailleCommand::AppendToCharacter { column } => BrailleCommand::AppendToCharacter { column: self.text_column(column) },
+      BrailleCommand::RectangularCopyToCharacter { column } => BrailleCommand::RectangularCopyToCharacter { column: self.text_column(column) },
+      BrailleCommand::LinearCopyToCharacter { column } => BrailleCommand::LinearCopyToCharacter { column: self.text_column(column) },
+      BrailleCommand::DescribeCharacter { column } => BrailleCommand::DescribeCharacter { column: self.text_column(column) },
+      BrailleCommand::StartSelection { column } => BrailleCommand::StartSelection { column: self.text_column(column) },
+      BrailleCommand::EndSelection { column } => BrailleCommand::EndSelection { column: self.text_column(column) },
+      BrailleCommand::RouteSpeechCursorToCharacter { column } => BrailleCommand::RouteSpeechCursorToCharacter { column: self.text_column(column) },
+      command => command,
+    }
+  }
+  fn write_section(&mut self, start: usize, braille: &Array1<u8>, text_columns: &[u16]) {
+    let cells = self.braille_matrix.len();
+    let mut braille_cells = self.braille_matrix.view_mut().into_shape_with_order(cells).unwrap();
+    braille_cells.slice_mut(s![start..start+braille.len()]).assign(braille);
+    self.text_columns[start..start+text_columns.len()].copy_from_slice(text_columns);
+  }
+  fn render(&self, cursor_dots: u8) -> Array2<u8> {
+    let mut new_matrix = self.braille_matrix.clone();
+    if let Some(cursor_position) = self.cursor_position {
+      let cells = new_matrix.len();
+      let mut braille_cells = new_matrix.view_mut().into_shape_with_order(cells).unwrap();
+      if let Some(cell) = braille_cells.get_mut(cursor_position as usize) {
+        *cell |= cursor_dots;
+      }
+    };
+    new_matrix
+  }
 }
 struct ServerState {
   columns: u8,
   lines: u8,
-  cursor_position: Option<u16>,
-  braille_matrix: Array2<u8>
+  next_client_id: ClientId,
+  sessions: HashMap<ClientId, ClientSession>,
+  ttys: TtyTree,
+  raw_client: Option<ClientId>,
+  suspend_client: Option<ClientId>,
+  parameters: ParameterStore,
+  shown_matrix: Option<Array2<u8>>
+}
+impl ServerState {
+  fn session_mut(&mut self, client: ClientId) -> Result<&mut ClientSession, ErrorCode> {
+    if !self.ttys.is_in_tty_mode(client) {
+      return Err(ErrorCode::IllegalInstruction);
+    }
+    self.sessions.get_mut(&client).ok_or(ErrorCode::IllegalInstruction)
+  }
+  fn check_suspended(&self) -> Result<(), ErrorCode> {
+    match self.suspend_client {
+      Some(_) => Err(ErrorCode::DeviceBusy),
+      None => Ok(()),
+    }
+  }
+  fn check_device(&self, client: ClientId) -> Result<(), ErrorCode> {
+    if self.raw_client == Some(client) || self.suspend_client == Some(client) {
+      return Err(ErrorCode::IllegalInstruction);
+    }
+    if self.raw_client.is_some() || self.suspend_client.is_some() {
+      return Err(ErrorCode::DeviceBusy);
+    }
+    Ok(())
+  }
+  async fn release_device(&mut self, client: ClientId, control_tx: Option<&mpsc::Sender<DriverControl>>) {
+    if self.raw_client == Some(client) {
+      self.raw_client = None;
+      self.shown_matrix = None;
+    }
+    if self.suspend_client == Some(client) {
+      self.suspend_client = None;
+      self.shown_matrix = None;
+      if let Some(control_tx) = control_tx {
+        let _ = control_tx.send(DriverControl::Resume).await;
+      }
+      self.update_parameter(0, &ParameterValue::DeviceOnline(true));
+    }
+  }
+  fn resize(&mut self, columns: u8, lines: u8) {
+    if (columns, lines) == (self.columns, self.lines) {
+      return;
+    }
+    self.columns = columns;
+    self.lines = lines;
+    for session in self.sessions.values_mut() {
+      session.braille_matrix = Array2::zeros((lines as usize, columns as usize));
+      session.text_columns = (0..u16::from(columns) * u16::from(lines)).collect();
+      session.cursor_position = None;
+      session.written_texts.clear();
+    }
+    self.shown_matrix = None;
+  }
+  fn update_parameter(&mut self, sub_parameter: u64, value: &ParameterValue) {
+    if self.parameters.set_global(sub_parameter, value) {
+      self.notify_parameter(None, value.parameter().into_u32(), sub_parameter, Scope::Global);
+    }
+  }
+  fn notify_parameter(&self, origin: Option<ClientId>, parameter: u32, sub_parameter: u64, scope: Scope) {
+    for update in self.parameters.updates(origin, parameter, sub_parameter, scope) {
+      if let Some(session) = self.sessions.get(&update.client) {
+        let flags = if update.global {
+          ParameterValueFlags::Global
+        }
+        else {
+          ParameterValueFlags::empty()
+        };
+        let _ = session.packet_tx.try_send(ServerPacketData::ParameterUpdate { flags, parameter, sub_parameter, value: update.value });
+      }
+    }
+  }
+  fn toggle_contracted(&mut self, flags: &KeycodeFlags) -> Option<ClientId> {
+    let client = self.ttys.focused_client()?;
+    let contracted = if flags.contains(KeycodeFlags::ToggleOn) {
+      true
+    }
+    else if flags.contains(KeycodeFlags::ToggleOff) {
+      false
+    }
+    else {
+      self.parameters.value(Some(client), Parameter::LiteraryBraille) != ParameterValue::LiteraryBraille(true)
+    };
+    let parameter = Parameter::LiteraryBraille.into_u32();
+    let scope = self.parameters.set(client, parameter, 0, false, ParameterValue::LiteraryBraille(contracted).encode()).ok()?;
+    self.notify_parameter(None, parameter, 0, scope);
+    Some(client)
+  }
+  fn translation_settings(&self, client: ClientId) -> TranslationSettings {
+    let computer_braille_table = match self.parameters.value(Some(client), Parameter::ComputerBrailleTable) {
+      ParameterValue::ComputerBrailleTable(table) => table,
+      _ => String::new(),
+    };
+    let literary_braille_table = match self.parameters.value(Some(client), Parameter::LiteraryBrailleTable) {
+      ParameterValue::LiteraryBrailleTable(table) => table,
+      _ => String::new(),
+    };
+    let contracted = self.parameters.value(Some(client), Parameter::LiteraryBraille) == ParameterValue::LiteraryBraille(true);
+    TranslationSettings { computer_braille_table, literary_braille_table, contracted }
+  }
+  async fn retranslate(&mut self, client: ClientId, translator_tx: &mpsc::Sender<TranslatorJob>) -> Result<(), ServerError> {
+    let tables = self.translation_settings(client).tables();
+    let Some(written_texts) = self.sessions.get(&client).map(|session| session.written_texts.clone()) else {
+      return Ok(());
+    };
+    for written in written_texts {
+      let (mut braille_cells, text_columns, cursor) = match translate_region(translator_tx, tables.clone(), written.text, written.region, written.cursor).await {
+        Ok(rendered) => rendered,
+        Err(ServerError::Translation(_)) => continue,
+        Err(error) => return Err(error),
+      };
+      apply_masks(&mut braille_cells, written.and, written.or);
+      let Some(session) = self.sessions.get_mut(&client) else {
+        return Ok(());
+      };
+      session.write_section(written.region.0 as usize, &braille_cells, &text_columns);
+      if let Some(cursor) = cursor {
+        session.cursor_position = cursor.checked_sub(1).map(|position| position as u16);
+      }
+    }
+    Ok(())
+  }
+  fn dispatch_keycode(&self, mut keycode: Keycode) {
+    let key = keycode.clone().into_u64();
+    let session = self.ttys.focus_chain().into_iter()
+      .filter_map(|client| self.sessions.get(&client))
+      .find(|session| session.key_ranges.contains(key));
+    if let Some(session) = session {
+      keycode.braille_command = keycode.braille_command.map(|command| session.map_routing(command));
+      let _ = session.packet_tx.try_send(ServerPacketData::Key { key: keycode });
+    }
+  }
+  async fn refresh(&mut self, braille_tx: &mpsc::Sender<Array2<u8>>) -> Result<(), ServerError> {
+    if self.raw_client.is_some() || self.suspend_client.is_some() {
+      return Ok(());
+    }
+    let focused_client = self.ttys.focused_client();
+    let new_matrix = match focused_client.and_then(|client| self.sessions.get(&client)) {
+      Some(session) => {
+        let cursor_dots = match self.parameters.value(focused_client, Parameter::CursorDots) {
+          ParameterValue::CursorDots(dots) => dots,
+          _ => 0,
+        };
+        session.render(cursor_dots)
+      },
+      None => Array2::zeros((self.lines as usize, self.columns as usize)),
+    };
+    if self.shown_matrix.as_ref() != Some(&new_matrix) {
+      self.shown_matrix = Some(new_matrix.clone());
+      braille_tx.send(new_matrix).await?;
+    }
+    Ok(())
+  }
 }
 enum Command {
   GetDriverName { result_tx: oneshot::Sender<String> },
   GetModelId { result_tx: oneshot::Sender<String> },
   GetDimentions { result_tx: oneshot::Sender<(u8, u8)> },
-  SetCursor { position: Option<u16>, result_tx: oneshot::Sender<()> },
-  SetBrailleMatrixSection { start: u16, length: u16, braille: Array1<u8>, result_tx: oneshot::Sender<()> },
-  SetKeycodeHandler { keycode_tx: mpsc::Sender<Keycode> },
+  RegisterClient { packet_tx: mpsc::Sender<ServerPacketData>, result_tx: oneshot::Sender<ClientId> },
+  UnregisterClient { client: ClientId },
+  EnterTtyMode { client: ClientId, ttys: Vec<u32>, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  LeaveTtyMode { client: ClientId, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  SetFocus { client: ClientId, tty: u32, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  IgnoreKeyRanges { client: ClientId, ranges: Vec<(u64, u64)>, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  AcceptKeyRanges { client: ClientId, ranges: Vec<(u64, u64)>, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  EnterRawMode { client: ClientId, driver: Vec<u8>, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  LeaveRawMode { client: ClientId, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  SendRawPacket { client: ClientId, packet: Vec<u8>, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  SuspendDriver { client: ClientId, driver: Vec<u8>, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  ResumeDriver { client: ClientId, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  GetTranslationSettings { client: ClientId, result_tx: oneshot::Sender<TranslationSettings> },
+  GetParameter { client: ClientId, parameter: u32, sub_parameter: u64, global: bool, result_tx: oneshot::Sender<Result<Vec<u8>, ErrorCode>> },
+  SetParameter { client: ClientId, parameter: u32, sub_parameter: u64, global: bool, value: Vec<u8>, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  SubscribeParameter { client: ClientId, parameter: u32, sub_parameter: u64, global: bool, include_self: bool, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  UnsubscribeParameter { client: ClientId, parameter: u32, sub_parameter: u64, global: bool, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  SetCursor { client: ClientId, position: Option<u16>, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+  SetBrailleMatrixSection { client: ClientId, start: u16, length: u16, braille: Array1<u8>, text_columns: Vec<u16>, written: Option<WrittenText>, result_tx: oneshot::Sender<Result<(), ErrorCode>> },
+}
+struct TranslationSettings {
+  computer_braille_table: String,
+  literary_braille_table: String,
+  contracted: bool,
+}
+impl TranslationSettings {
+  fn tables(&self) -> String {
+    let table = if self.contracted {
+      &self.literary_braille_table
+    }
+    else {
+      &self.computer_braille_table
+    };
+    format!("{table},braille-patterns.cti")
+  }
+}
+struct ClientRegistration {
+  client: ClientId,
+  command_tx: mpsc::Sender<Command>,
+}
+impl Drop for ClientRegistration {
+  fn drop(&mut self) {
+    let client = self.client;
+    let command_tx = self.command_tx.clone();
+    tokio::spawn(async move {
+      let _ = command_tx.send(Command::UnregisterClient { client }).await;
+    });
+  }
 }
 pub struct LouisRequest {
   pub tables: String,
   pub text: String,
   pub backwards: bool,
-  pub result_tx: oneshot::Sender<String>
+  pub result_tx: oneshot::Sender<Result<String, ServerError>>
+}
+async fn with_packet_timeout<T, F: Future<Output = Result<T, std::io::Error>>>(packet_timeout: Duration, future: F) -> Result<T, ServerError> {
+  Ok(timeout(packet_timeout, future).await.map_err(|_| std::io::Error::from(std::io::ErrorKind::TimedOut))??)
 }
-async fn read_packet<T: AsyncRead + Unpin>(reader: &mut T) -> Result<ClientPacket, std::io::Error> {
-  let mut buffer: Vec<u8> = vec![0; 4];
-  reader.read_exact(&mut buffer).await?;
+async fn read_packet<T: AsyncRead + Unpin>(reader: &mut T, packet_timeout: Duration) -> Result<ClientPacket, ServerError> {
+  let mut buffer: Vec<u8> = vec![0; 8];
+  buffer[0] = reader.read_u8().await?;
+  with_packet_timeout(packet_timeout, reader.read_exact(&mut buffer[1..8])).await?;
   let size: usize = u32::from_be_bytes(buffer[0..4].try_into().unwrap()) as _;
+  if size > MAX_PACKET_SIZE {
+    with_packet_timeout(packet_timeout, tokio::io::copy(&mut (&mut *reader).take(size as u64), &mut tokio::io::sink())).await?;
+    let code = if buffer[4..8] == *b"\0\0\0p" {
+      ErrorCode::DriverPacketTooLarge
+    }
+    else {
+      ErrorCode::InvalidPacket
+    };
+    return Err(ServerError::MalformedPacket { code, packet: buffer[4..8].to_vec() });
+  }
   buffer.resize(size + 8, 0);
-  reader.read_exact(&mut buffer[4..]).await?;
-  println!("Packet read: {:?}", &buffer);
-  let mut cursor = Cursor::new(buffer);
-  let packet = ClientPacket::read(&mut cursor).unwrap();
-  println!("{:?}", packet);
+  with_packet_timeout(packet_timeout, reader.read_exact(&mut buffer[8..])).await?;
+  let mut cursor = Cursor::new(&buffer);
+  let packet = match ClientPacket::read(&mut cursor) {
+    Ok(packet) => packet,
+    Err(_) => {
+      let code = if PacketType::read(&mut Cursor::new(&buffer[4..8])).is_ok() {
+        ErrorCode::InvalidPacket
+      }
+      else {
+        ErrorCode::UnknownInstruction
+      };
+      return Err(ServerError::MalformedPacket { code, packet: buffer[4..].to_vec() });
+    }
+  };
   Ok(packet)
 }
-async fn write_packet<T: AsyncWrite + Unpin>(packet: ServerPacket, writer: &mut T) -> Result<(), std::io::Error> {
+async fn write_packet<T: AsyncWrite + Unpin>(packet: ServerPacket, writer: &mut T) -> Result<(), ServerError> {
   let mut cursor = Cursor::new(Vec::new());
-  packet.write(&mut cursor).unwrap();
+  packet.write(&mut cursor)?;
   let data = cursor.into_inner();
-  println!("Writing packet: {:?}", packet);
-  println!("{:?}", data);
-  writer.write(&data).await?;
+  writer.write_all(&data).await?;
   writer.flush().await?;
   Ok(())
 }
-fn louis_runner(mut request_rx: mpsc::Receiver<LouisRequest>) {
-  let louis = Louis::new().unwrap();
-  while let Some(request) = request_rx.blocking_recv() {
-    let mode = if request.backwards {
-      0
+async fn write_result<T: AsyncWrite + Unpin>(result: Result<(), ErrorCode>, writer: &mut T) -> Result<(), ServerError> {
+  match result {
+    Ok(()) => write_packet(ServerPacket { data: ServerPacketData::Ack }, writer).await,
+    Err(code) => write_packet(ServerPacket { data: ServerPacketData::Error { code }}, writer).await,
+  }
+}
+fn packet_bytes(packet: &ClientPacket) -> Result<Vec<u8>, ServerError> {
+  let mut cursor = Cursor::new(Vec::new());
+  packet.write(&mut cursor)?;
+  Ok(cursor.into_inner().split_off(4))
+}
+async fn report_write_result<T: AsyncWrite + Unpin>(result: Result<(), ErrorCode>, packet: &[u8], writer: &mut T) -> Result<(), ServerError> {
+  match result {
+    Ok(()) => Ok(()),
+    Err(code) => write_packet(ServerPacket { data: ServerPacketData::Exception { code, packet: packet.to_vec() }}, writer).await,
+  }
+}
+async fn report_malformed_packet<T: AsyncWrite + Unpin>(error: ServerError, writer: &mut T) -> Result<(), ServerError> {
+  match error {
+    ServerError::MalformedPacket { code, packet } => write_packet(ServerPacket { data: ServerPacketData::Exception { code, packet }}, writer).await,
+    error => Err(error),
+  }
+}
+enum TranslatorJob {
+  Translate { tables: String, text: String, result_tx: oneshot::Sender<Result<Translation, ServerError>> },
+  Request(LouisRequest),
+}
+fn translator_runner(factory: TranslatorFactory, mut job_rx: mpsc::Receiver<TranslatorJob>, ready_tx: oneshot::Sender<Result<(), ServerError>>) {
+  let translator = match factory() {
+    Ok(translator) => translator,
+    Err(error) => {
+      let _ = ready_tx.send(Err(error));
+      return;
+    }
+  };
+  let _ = ready_tx.send(Ok(()));
+  while let Some(job) = job_rx.blocking_recv() {
+    match job {
+      TranslatorJob::Translate { tables, text, result_tx } => {
+        let _ = result_tx.send(translator.translate(&tables, &text));
+      },
+      TranslatorJob::Request(request) => {
+        let result = if request.backwards {
+          request.text.chars()
+            .map(|char| braille_pattern(char).ok_or_else(|| ServerError::Translation(format!("{char:?} is not a braille pattern"))))
+            .collect::<Result<Vec<u8>, ServerError>>()
+            .and_then(|cells| translator.back_translate(&request.tables, &cells))
+            .map(|translation| translation.text)
+        }
+        else {
+          translator.translate(&request.tables, &request.text).map(|translation| braille_string(&translation.cells))
+        };
+        let _ = request.result_tx.send(result);
+      },
+    }
+  }
+}
+fn decode_wide(text: &[u8], width: usize, little_endian: bool) -> String {
+  let units = text.chunks(width).map(|chunk| match (chunk.len() == width, little_endian) {
+    (false, _) => 0xfffd,
+    (true, true) => chunk.iter().rev().fold(0, |unit, byte| unit << 8 | u32::from(*byte)),
+    (true, false) => chunk.iter().fold(0, |unit, byte| unit << 8 | u32::from(*byte)),
+  });
+  if width == 2 {
+    char::decode_utf16(units.map(|unit| unit as u16)).map(|char| char.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+  }
+  else {
+    units.map(|unit| char::from_u32(unit).unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+  }
+}
+fn decode_text(text: &[u8], charset: &str) -> Result<String, ServerError> {
+  let little_endian = cfg!(target_endian = "little");
+  match charset.to_ascii_uppercase().as_str() {
+    "WCHAR_T" => Ok(decode_wide(text, if cfg!(windows) { 2 } else { 4 }, little_endian)),
+    "UCS-4" | "UCS-4BE" | "UTF-32BE" => Ok(decode_wide(text, 4, false)),
+    "UCS-4LE" | "UTF-32LE" => Ok(decode_wide(text, 4, true)),
+    "UCS-4-INTERNAL" => Ok(decode_wide(text, 4, little_endian)),
+    _ => decode_lossy(text, charset).map_err(|error| ServerError::Charset(format!("{charset}: {error}"))),
+  }
+}
+async fn translate(translator_tx: &mpsc::Sender<TranslatorJob>, tables: String, text: String) -> Result<Translation, ServerError> {
+  let (result_tx, result_rx) = oneshot::channel();
+  translator_tx.send(TranslatorJob::Translate { tables, text, result_tx }).await?;
+  result_rx.await?
+}
+async fn translate_positions(translator_tx: &mpsc::Sender<TranslatorJob>, tables: String, text: String) -> Result<Translation, ServerError> {
+  let characters: Vec<char> = text.chars().collect();
+  let mut cells = Vec::with_capacity(characters.len());
+  let mut positions = Vec::with_capacity(characters.len()+1);
+  let mut input_positions = Vec::with_capacity(characters.len());
+  let mut start = 0;
+  while start < characters.len() {
+    let is_pattern = braille_pattern(characters[start]).is_some();
+    let end = characters[start..].iter().position(|char| braille_pattern(*char).is_some() != is_pattern).map_or(characters.len(), |length| start + length);
+    if is_pattern {
+      for (character, dots) in characters[start..end].iter().enumerate().filter_map(|(offset, char)| braille_pattern(*char).map(|dots| (start + offset, dots))) {
+        positions.push(cells.len());
+        input_positions.push(character);
+        cells.push(dots);
+      }
     }
     else {
-      ::louis::modes::DOTS_UNICODE
+      let translation = translate(translator_tx, tables.clone(), characters[start..end].iter().collect()).await?;
+      let mut previous = cells.len();
+      for position in translation.positions.into_iter().take(end-start) {
+        previous = (cells.len() + position).clamp(previous, cells.len() + translation.cells.len());
+        positions.push(previous);
+      }
+      positions.resize(end, previous);
+      input_positions.extend(translation.input_positions.into_iter().take(translation.cells.len()).map(|position| start + position.min(end-start-1)));
+      input_positions.resize(cells.len() + translation.cells.len(), end-1);
+      cells.extend(translation.cells);
+    }
+    start = end;
+  }
+  positions.push(cells.len());
+  Ok(Translation { cells, positions, input_positions })
+}
+async fn translate_region(translator_tx: &mpsc::Sender<TranslatorJob>, tables: String, text: String, region: (u32, u32), cursor: Option<u32>) -> Result<(Array1<u8>, Vec<u16>, Option<u32>), ServerError> {
+  let Translation { mut cells, positions, input_positions } = translate_positions(translator_tx, tables, text).await?;
+  let characters = positions.len()-1;
+  let mut cursor = cursor;
+  if let Some(offset) = cursor.and_then(|cursor| cursor.checked_sub(region.0+1)).filter(|offset| *offset < region.1) {
+    let offset = offset as usize;
+    let cell = (positions[offset.min(characters)] + offset.saturating_sub(characters)).min(region.1 as usize - 1);
+    cursor = Some(region.0 + cell as u32 + 1);
+  }
+  let text_columns = (0..region.1 as usize).map(|cell| {
+    let character = match cell.checked_sub(cells.len()) {
+      Some(padding) => characters + padding,
+      None => input_positions[cell],
     };
-    let result = louis.translate_simple(&request.tables, &request.text, request.backwards, mode);
-    request.result_tx.send(result).unwrap();
+    (region.0 as usize + character) as u16
+  }).collect();
+  cells.resize(region.1 as usize, 0);
+  Ok((Array1::from(cells), text_columns, cursor))
+}
+fn apply_masks(braille_cells: &mut Array1<u8>, and: Option<Vec<u8>>, or: Option<Vec<u8>>) {
+  if let Some(mut and) = and {
+    and.resize(braille_cells.len(), 0xff);
+    *braille_cells &= &Array1::from(and);
+  };
+  if let Some(mut or) = or {
+    or.resize(braille_cells.len(), 0);
+    *braille_cells |= &Array1::from(or);
+  };
+}
+async fn dimensions(command_tx: &mpsc::Sender<Command>) -> Result<(u8, u8), ServerError> {
+  let (result_tx, result_rx) = oneshot::channel();
+  command_tx.send(Command::GetDimentions { result_tx }).await?;
+  Ok(result_rx.await?)
+}
+async fn table_loads(translator_tx: &mpsc::Sender<TranslatorJob>, table: &str) -> Result<bool, ServerError> {
+  match translate(translator_tx, table.to_owned(), "a".to_owned()).await {
+    Ok(translation) => Ok(!translation.cells.is_empty()),
+    Err(ServerError::Translation(_)) => Ok(false),
+    Err(error) => Err(error),
   }
 }
-async fn handle_state(backend: ServerBackend, mut command_rx: mpsc::Receiver<Command>, louis_tx: mpsc::Sender<LouisRequest>) {
-  let mut state = ServerState { columns: backend.columns, lines: backend.lines, cursor_position: None, braille_matrix: Array2::zeros((backend.lines as usize, backend.columns as usize)) };
-  let new_matrix = state.braille_matrix.clone();
-  backend.braille_tx.send(new_matrix).await.unwrap();
-  let keycode_handler: Arc<Mutex<Option<mpsc::Sender<Keycode>>>> = Arc::new(Mutex::new(None));
-  let keycode_handler2 = keycode_handler.clone();
+async fn handle_state(backend: ServerBackend, mut command_rx: mpsc::Receiver<Command>, translator_tx: mpsc::Sender<TranslatorJob>, computer_braille_table: String, literary_braille_table: String) -> Result<(), ServerError> {
+  let mut state = ServerState { columns: backend.columns, lines: backend.lines, next_client_id: 1, sessions: HashMap::new(), ttys: TtyTree::default(), raw_client: None, suspend_client: None, parameters: ParameterStore::default(), shown_matrix: None };
+  state.parameters.set_global(0, &ParameterValue::ServerVersion(8));
+  state.parameters.set_global(0, &ParameterValue::DriverName(backend.driver_name.clone()));
+  state.parameters.set_global(0, &ParameterValue::DriverCode(backend.driver_name.clone()));
+  state.parameters.set_global(0, &ParameterValue::DeviceModel(backend.model_id.clone()));
+  state.parameters.set_global(0, &ParameterValue::DisplaySize { columns: u32::from(backend.columns), lines: u32::from(backend.lines) });
+  state.parameters.set_global(0, &ParameterValue::DeviceOnline(true));
+  state.parameters.set_global(0, &ParameterValue::ComputerBrailleTable(computer_braille_table));
+  state.parameters.set_global(0, &ParameterValue::LiteraryBrailleTable(literary_braille_table));
+  state.refresh(&backend.braille_tx).await?;
   let mut keycode_rx = backend.keycode_rx;
-  tokio::spawn(async move {
-    while let Some(keycode) = keycode_rx.recv().await {
-      if let Some(keycode_tx) = keycode_handler2.lock().await.as_ref() {
-        let _ = keycode_tx.send(keycode).await;
-      }
-    }
-  });
+  let (raw_tx, mut raw_rx) = match backend.raw {
+    Some(raw) => (Some(raw.packet_tx), Some(raw.packet_rx)),
+    None => (None, None),
+  };
+  let control_tx = backend.control_tx;
+  let mut parameter_rx = backend.parameter_rx;
+  let mut focus_rx = backend.focus_rx;
+  let mut forwarding_tasks = JoinSet::new();
   let mut louis_rx = backend.louis_rx;
-  tokio::spawn(async move {
+  let request_tx = translator_tx.clone();
+  forwarding_tasks.spawn(async move {
     while let Some(request) = louis_rx.recv().await {
-      louis_tx.send(request).await.unwrap();
+      if request_tx.send(TranslatorJob::Request(request)).await.is_err() {
+        break;
+      }
     }
   });
-  while let Some(command) = command_rx.recv().await {
+  loop {
+    let command = tokio::select! {
+      command = command_rx.recv() => match command {
+        Some(command) => command,
+        None => break,
+      },
+      Some(keycode) = keycode_rx.recv() => {
+        if keycode.braille_command == Some(BrailleCommand::ToggleContracted) {
+          if let Some(client) = state.toggle_contracted(&keycode.flags) {
+            state.retranslate(client, &translator_tx).await?;
+            state.refresh(&backend.braille_tx).await?;
+          }
+        }
+        else {
+          state.dispatch_keycode(keycode);
+        }
+        continue;
+      },
+      Some(change) = async { parameter_rx.as_mut()?.recv().await } => {
+        if let ParameterValue::DisplaySize { columns, lines } = change.value {
+          let (Ok(columns), Ok(lines)) = (u8::try_from(columns), u8::try_from(lines)) else {
+            continue;
+          };
+          state.resize(columns, lines);
+        }
+        state.update_parameter(change.sub_parameter, &change.value);
+        state.refresh(&backend.braille_tx).await?;
+        continue;
+      },
+      Some(tty) = async { focus_rx.as_mut()?.recv().await } => {
+        state.ttys.set_root_focus(tty);
+        state.refresh(&backend.braille_tx).await?;
+        continue;
+      },
+      Some(packet) = async { raw_rx.as_mut()?.recv().await } => {
+        if let Some(session) = state.raw_client.and_then(|client| state.sessions.get(&client)) {
+          let _ = session.packet_tx.try_send(ServerPacketData::Packet { packet });
+        }
+        continue;
+      },
+    };
     match command {
-      Command::GetDriverName { result_tx } => result_tx.send(backend.driver_name.clone()).unwrap(),
-      Command::GetModelId { result_tx } => result_tx.send(backend.model_id.clone()).unwrap(),
-      Command::GetDimentions { result_tx } => result_tx.send((state.columns, state.lines)).unwrap(),
-      Command::SetCursor { position, result_tx } => {
-        state.cursor_position = position;
-        let mut new_matrix = state.braille_matrix.clone();
-        if let Some(position) = position {
-          let mut braille_cells = new_matrix.view_mut().into_shape_with_order(state.lines as usize*state.columns as usize).unwrap();
-          let cell = braille_cells.get_mut(position as usize).unwrap();
-          *cell |= 192;
+      Command::GetDriverName { result_tx } => {
+        let _ = result_tx.send(backend.driver_name.clone());
+      },
+      Command::GetModelId { result_tx } => {
+        let _ = result_tx.send(backend.model_id.clone());
+      },
+      Command::GetDimentions { result_tx } => {
+        let _ = result_tx.send((state.columns, state.lines));
+      },
+      Command::RegisterClient { packet_tx, result_tx } => {
+        let client = state.next_client_id;
+        state.next_client_id += 1;
+        state.sessions.insert(client, ClientSession::new(packet_tx, state.columns, state.lines));
+        let _ = result_tx.send(client);
+      },
+      Command::UnregisterClient { client } => {
+        let _ = state.ttys.leave(client);
+        state.sessions.remove(&client);
+        state.parameters.remove_client(client);
+        state.release_device(client, control_tx.as_ref()).await;
+        state.refresh(&backend.braille_tx).await?;
+      },
+      Command::EnterTtyMode { client, ttys, result_tx } => {
+        let result = state.ttys.enter(client, ttys);
+        if result.is_ok() && let Some(session) = state.sessions.get_mut(&client) {
+          session.key_ranges = KeyRanges::default();
+        }
+        state.refresh(&backend.braille_tx).await?;
+        let _ = result_tx.send(result);
+      },
+      Command::LeaveTtyMode { client, result_tx } => {
+        let result = state.ttys.leave(client);
+        if let Some(session) = state.sessions.get_mut(&client) {
+          *session = ClientSession::new(session.packet_tx.clone(), state.columns, state.lines);
+        }
+        state.refresh(&backend.braille_tx).await?;
+        let _ = result_tx.send(result);
+      },
+      Command::SetFocus { client, tty, result_tx } => {
+        let result = state.ttys.set_focus(client, tty);
+        state.refresh(&backend.braille_tx).await?;
+        let _ = result_tx.send(result);
+      },
+      Command::IgnoreKeyRanges { client, ranges, result_tx } => {
+        let result = state.session_mut(client).and_then(|session| session.key_ranges.ignore(&ranges));
+        let _ = result_tx.send(result);
+      },
+      Command::AcceptKeyRanges { client, ranges, result_tx } => {
+        let result = state.session_mut(client).and_then(|session| session.key_ranges.accept(&ranges));
+        let _ = result_tx.send(result);
+      },
+      Command::EnterRawMode { client, driver, result_tx } => {
+        let result = if raw_tx.is_none() {
+          Err(ErrorCode::OperationNotSupported)
+        }
+        else if driver != backend.driver_name.as_bytes() {
+          Err(ErrorCode::InvalidParameter)
+        }
+        else {
+          state.check_device(client).map(|()| state.raw_client = Some(client))
+        };
+        let _ = result_tx.send(result);
+      },
+      Command::LeaveRawMode { client, result_tx } => {
+        let result = if state.raw_client == Some(client) {
+          state.release_device(client, control_tx.as_ref()).await;
+          state.refresh(&backend.braille_tx).await?;
+          Ok(())
+        }
+        else {
+          Err(ErrorCode::IllegalInstruction)
+        };
+        let _ = result_tx.send(result);
+      },
+      Command::SendRawPacket { client, packet, result_tx } => {
+        let result = match raw_tx.as_ref() {
+          Some(raw_tx) if state.raw_client == Some(client) => raw_tx.send(packet).await.map_err(|_| ErrorCode::OperationNotSupported),
+          _ => Err(ErrorCode::IllegalInstruction),
         };
-        backend.braille_tx.send(new_matrix).await.unwrap();
-        result_tx.send(()).unwrap();
-      },
-      Command::SetBrailleMatrixSection { start, length, braille, result_tx } => {
-        let mut braille_cells = state.braille_matrix.view_mut().into_shape_with_order(state.lines as usize*state.columns as usize).unwrap();
-        let mut slice = braille_cells.slice_mut(s![start as i32..(start+length) as i32]);
-        slice.assign(&braille);
-        let mut new_matrix = state.braille_matrix.clone();
-        if let Some(cursor_position) = state.cursor_position {
-          let mut braille_cells = new_matrix.view_mut().into_shape_with_order(state.lines as usize*state.columns as usize).unwrap();
-          let cell = braille_cells.get_mut(cursor_position as usize).unwrap();
-          *cell |= 192;
+        let _ = result_tx.send(result);
+      },
+      Command::SuspendDriver { client, driver, result_tx } => {
+        let result = match control_tx.as_ref() {
+          None => Err(ErrorCode::OperationNotSupported),
+          Some(_) if driver != backend.driver_name.as_bytes() => Err(ErrorCode::InvalidParameter),
+          Some(control_tx) => match state.check_device(client) {
+            Ok(()) => match control_tx.send(DriverControl::Suspend).await {
+              Ok(()) => {
+                state.suspend_client = Some(client);
+                state.update_parameter(0, &ParameterValue::DeviceOnline(false));
+                Ok(())
+              },
+              Err(_) => Err(ErrorCode::OperationNotSupported),
+            },
+            Err(code) => Err(code),
+          },
+        };
+        let _ = result_tx.send(result);
+      },
+      Command::ResumeDriver { client, result_tx } => {
+        let result = if state.suspend_client == Some(client) {
+          state.release_device(client, control_tx.as_ref()).await;
+          state.refresh(&backend.braille_tx).await?;
+          Ok(())
+        }
+        else {
+          Err(ErrorCode::IllegalInstruction)
         };
-        backend.braille_tx.send(new_matrix).await.unwrap();
-        result_tx.send(()).unwrap();
+        let _ = result_tx.send(result);
+      },
+      Command::GetTranslationSettings { client, result_tx } => {
+        let _ = result_tx.send(state.translation_settings(client));
+      },
+      Command::GetParameter { client, parameter, sub_parameter, global, result_tx } => {
+        let _ = result_tx.send(state.parameters.get(client, parameter, sub_parameter, global));
+      },
+      Command::SetParameter { client, parameter, sub_parameter, global, value, result_tx } => {
+        let result = state.parameters.set(client, parameter, sub_parameter, global, value)
+          .map(|scope| state.notify_parameter(Some(client), parameter, sub_parameter, scope));
+        state.refresh(&backend.braille_tx).await?;
+        let _ = result_tx.send(result);
+      },
+      Command::SubscribeParameter { client, parameter, sub_parameter, global, include_self, result_tx } => {
+        let _ = result_tx.send(state.parameters.subscribe(client, parameter, sub_parameter, global, include_self));
+      },
+      Command::UnsubscribeParameter { client, parameter, sub_parameter, global, result_tx } => {
+        let _ = result_tx.send(state.parameters.unsubscribe(client, parameter, sub_parameter, global));
+      },
+      Command::SetCursor { client, position, result_tx } => {
+        let cells = state.columns as usize * state.lines as usize;
+        let result = state.check_suspended()
+          .and_then(|()| if position.is_some_and(|position| position as usize >= cells) { Err(ErrorCode::InvalidParameter) } else { Ok(()) })
+          .and_then(|()| state.session_mut(client))
+          .map(|session| {
+          session.cursor_position = position;
+          for written in &mut session.written_texts {
+            written.cursor = None;
+          }
+        });
+        state.refresh(&backend.braille_tx).await?;
+        let _ = result_tx.send(result);
+      },
+      Command::SetBrailleMatrixSection { client, start, length, braille, text_columns, written, result_tx } => {
+        let cells = state.columns as usize * state.lines as usize;
+        let result = state.check_suspended()
+          .and_then(|()| if start as usize + length as usize > cells || braille.len() != length as usize || text_columns.len() != length as usize { Err(ErrorCode::InvalidParameter) } else { Ok(()) })
+          .and_then(|()| state.session_mut(client))
+          .map(|session| {
+          session.write_section(start as usize, &braille, &text_columns);
+          let end = u32::from(start) + u32::from(length);
+          session.written_texts.retain(|written| written.region.0 >= end || written.region.0 + written.region.1 <= u32::from(start));
+          session.written_texts.extend(written);
+        });
+        state.refresh(&backend.braille_tx).await?;
+        let _ = result_tx.send(result);
       },
-      Command::SetKeycodeHandler { keycode_tx } => *keycode_handler.lock().await = Some(keycode_tx),
     }
   }
+  Ok(())
 }
-async fn handle_connection(mut socket: TcpStream, auth_key: Option<String>, louis_tx: mpsc::Sender<LouisRequest>, command_tx: mpsc::Sender<Command>) -> Result<(), std::io::Error> {
+async fn handle_connection<S: AsyncRead + AsyncWrite + Send + Unpin + 'static>(mut socket: S, peer: Peer, auth: AuthPolicy, context: ConnectionContext) -> Result<(), ServerError> {
+  let ConnectionContext { auth_failures, packet_timeout, translator_tx, command_tx, connections: _ } = context;
   write_packet(ServerPacket { data: ServerPacketData::Version { version: 8 }}, &mut socket).await?;
-  let version_packet = read_packet(&mut socket).await?;
+  let version_packet = match read_packet(&mut socket, packet_timeout).await {
+    Ok(packet) => packet,
+    Err(error) => return report_malformed_packet(error, &mut socket).await,
+  };
   match version_packet.data {
     ClientPacketData::Version { version: 8 } => {},
     _ => {
@@ -142,84 +790,93 @@ async fn handle_connection(mut socket: TcpStream, auth_key: Option<String>, loui
       return Ok(());
     }
   }
-  let auth_type = if auth_key.is_some() {
-    AuthType::Key
-  }
-  else {
-    AuthType::None
-  };
-  write_packet(ServerPacket { data: ServerPacketData::Auth { auth_types: vec![auth_type] }}, &mut socket).await?;
-  if let Some(auth_key) = auth_key {
-    loop {
-      let auth_packet = read_packet(&mut socket).await?;
-      match auth_packet.data {
-        ClientPacketData::Auth { auth_type: AuthType::Key, key: client_key } => {
-          if String::from_utf8_lossy(&client_key) == auth_key {
-            write_packet(ServerPacket { data: ServerPacketData::Ack }, &mut socket).await?;
-            break;
-          }
-          else {
-            write_packet(ServerPacket { data: ServerPacketData::Error { code: ErrorCode::AuthenticationFailed }}, &mut socket).await?;
-            continue;
-          }
-        },
-        _ => {
-          write_packet(ServerPacket { data: ServerPacketData::Error { code: ErrorCode::BadProtocolVersion }}, &mut socket).await?;
-          return Ok(());
-        }
-      }
-    }
+  match authenticate(&mut socket, &auth, &auth_failures, &peer, packet_timeout).await {
+    Ok(true) => {},
+    Ok(false) => return Ok(()),
+    Err(error) => return report_malformed_packet(error, &mut socket).await,
   }
   let (result_tx, result_rx) = oneshot::channel();
-  command_tx.send(Command::GetDriverName { result_tx }).await.unwrap();
-  let driver_name = result_rx.await.unwrap();
+  command_tx.send(Command::GetDriverName { result_tx }).await?;
+  let driver_name = result_rx.await?;
   let (result_tx, result_rx) = oneshot::channel();
-  command_tx.send(Command::GetModelId { result_tx }).await.unwrap();
-  let model_id = result_rx.await.unwrap();
+  command_tx.send(Command::GetModelId { result_tx }).await?;
+  let model_id = result_rx.await?;
+  let (packet_tx, mut packet_rx) = mpsc::channel(32);
   let (result_tx, result_rx) = oneshot::channel();
-  command_tx.send(Command::GetDimentions { result_tx }).await.unwrap();
-  let (columns, lines) = result_rx.await.unwrap();
-  let (mut reader, writer) = socket.into_split();
+  command_tx.send(Command::RegisterClient { packet_tx, result_tx }).await?;
+  let registration = ClientRegistration { client: result_rx.await?, command_tx: command_tx.clone() };
+  let client = registration.client;
+  let (mut reader, writer) = tokio::io::split(socket);
   let writer = Arc::new(Mutex::new(writer));
   let writer2 = writer.clone();
-  let (keycode_tx, mut keycode_rx) = mpsc::channel(32);
-  command_tx.send(Command::SetKeycodeHandler { keycode_tx }).await.unwrap();
-  tokio::spawn(async move {
-    while let Some(keycode) = keycode_rx.recv().await {
-      write_packet(ServerPacket { data: ServerPacketData::Key { key: keycode }}, &mut *writer2.lock().await).await.unwrap();
+  let mut packet_task = JoinSet::new();
+  packet_task.spawn(async move {
+    while let Some(data) = packet_rx.recv().await {
+      if write_packet(ServerPacket { data }, &mut *writer2.lock().await).await.is_err() {
+        break;
+      }
     }
   });
   loop {
-    let packet = read_packet(&mut reader).await?;
+    let packet = read_packet(&mut reader, packet_timeout).await;
     let mut writer = writer.lock().await;
+    let packet = match packet {
+      Ok(packet) => packet,
+      Err(error) => {
+        report_malformed_packet(error, &mut *writer).await?;
+        continue;
+      }
+    };
+    let request_bytes = match packet.data {
+      ClientPacketData::Write { .. } | ClientPacketData::Packet { .. } => packet_bytes(&packet)?,
+      _ => Vec::new(),
+    };
     match packet.data {
-      ClientPacketData::Write { display_number, region, text, and, or, cursor, charset } => {
+      ClientPacketData::Write { display_number, region, text, and, or, mut cursor, charset } => {
         let text = match (text, charset) {
           (None, _) => None,
           (Some(text), None) => Some(String::from_utf8_lossy(&text).to_string()),
-          (Some(text), Some(charset)) => Some(decode_lossy(&text, &String::from_utf8_lossy(&charset)).unwrap())
+          (Some(text), Some(charset)) => match decode_text(&text, &String::from_utf8_lossy(&charset)) {
+            Ok(text) => Some(text),
+            Err(ServerError::Charset(_)) => {
+              report_write_result(Err(ErrorCode::InvalidParameter), &request_bytes, &mut *writer).await?;
+              continue;
+            },
+            Err(error) => return Err(error),
+          },
         };
+        let (columns, lines) = dimensions(&command_tx).await?;
+        let cells = u32::from(columns) * u32::from(lines);
         let region = match (region, text.as_ref()) {
-          (Some((start, length)), _) => (start-1, length),
-          (None, Some(text)) => (0, text.len() as u32),
-          (None, None) => (0, 0)
-        };
-        let mut braille_cells: Array1<u8> = Array1::zeros(region.1 as usize);
-        if let Some(text) = text {
-          let (result_tx, result_rx) = oneshot::channel();
-          louis_tx.send(LouisRequest { tables: "en-us-comp8.ctb,braille-patterns.cti".to_owned(), text: text.to_string(), backwards: false, result_tx }).await.unwrap();
-          let cells = result_rx.await.unwrap()
-            .chars()
-            .map(|char| (u32::from(char)-10240).try_into().unwrap())
-            .collect::<Vec<u8>>();
-          braille_cells.assign(&Array1::from(cells));
+          (Some((start, length)), _) => start.checked_sub(1).filter(|start| start.checked_add(length).is_some_and(|end| end <= cells)).map(|start| (start, length)),
+          (None, Some(text)) => Some((0, (text.chars().count() as u32).min(cells))),
+          (None, None) => Some((0, 0))
         };
-        if let Some(and) = and {
-          braille_cells &= &Array1::from(and);
+        let Some(region) = region.filter(|_| cursor.is_none_or(|cursor| cursor <= cells)) else {
+          report_write_result(Err(ErrorCode::InvalidParameter), &request_bytes, &mut *writer).await?;
+          continue;
         };
-        if let Some(or) = or {
-          braille_cells |= &Array1::from(or);
+        let (mut braille_cells, text_columns, written) = match text {
+          Some(text) => {
+            let (result_tx, result_rx) = oneshot::channel();
+            command_tx.send(Command::GetTranslationSettings { client, result_tx }).await?;
+            let tables = result_rx.await?.tables();
+            let written = WrittenText { text, region, cursor, and: and.clone(), or: or.clone() };
+            let (braille_cells, text_columns, mapped_cursor) = match translate_region(&translator_tx, tables, written.text.clone(), region, cursor).await {
+              Ok(rendered) => rendered,
+              Err(ServerError::Translation(_)) => {
+                report_write_result(Err(ErrorCode::InvalidParameter), &request_bytes, &mut *writer).await?;
+                continue;
+              },
+              Err(error) => return Err(error),
+            };
+            cursor = mapped_cursor;
+            (braille_cells, text_columns, Some(written))
+          },
+          None => (Array1::zeros(region.1 as usize), (0..region.1).map(|cell| (region.0 + cell) as u16).collect(), None),
         };
+        apply_masks(&mut braille_cells, and, or);
+        let mut result = Ok(());
         if let Some(cursor) = cursor {
           let (result_tx, result_rx) = oneshot::channel();
           let position = if cursor==0 {
@@ -228,40 +885,332 @@ async fn handle_connection(mut socket: TcpStream, auth_key: Option<String>, loui
           else {
             Some((cursor-1) as u16)
           };
-          command_tx.send(Command::SetCursor { position, result_tx }).await.unwrap();
-          result_rx.await.unwrap();
+          command_tx.send(Command::SetCursor { client, position, result_tx }).await?;
+          result = result_rx.await?;
         };
-        if region.1 != 0 {
+        if region.1 != 0 && result.is_ok() {
           let (result_tx, result_rx) = oneshot::channel();
-          command_tx.send(Command::SetBrailleMatrixSection { start: region.0 as u16, length: region.1 as u16, braille: braille_cells, result_tx }).await.unwrap();
-          result_rx.await.unwrap();
+          command_tx.send(Command::SetBrailleMatrixSection { client, start: region.0 as u16, length: region.1 as u16, braille: braille_cells, text_columns, written, result_tx }).await?;
+          result = result_rx.await?;
         };
+        report_write_result(result, &request_bytes, &mut *writer).await?;
+      },
+      ClientPacketData::EnterTtyMode { ttys, driver } => {
+        if driver.is_empty() {
+          let (result_tx, result_rx) = oneshot::channel();
+          command_tx.send(Command::EnterTtyMode { client, ttys, result_tx }).await?;
+          write_result(result_rx.await?, &mut *writer).await?;
+        }
+        else {
+          write_result(Err(ErrorCode::OperationNotSupported), &mut *writer).await?;
+        }
+      },
+      ClientPacketData::LeaveTtyMode => {
+        let (result_tx, result_rx) = oneshot::channel();
+        command_tx.send(Command::LeaveTtyMode { client, result_tx }).await?;
+        write_result(result_rx.await?, &mut *writer).await?;
+      },
+      ClientPacketData::SetFocus { tty } => {
+        let (result_tx, result_rx) = oneshot::channel();
+        command_tx.send(Command::SetFocus { client, tty, result_tx }).await?;
+        write_result(result_rx.await?, &mut *writer).await?;
+      },
+      ClientPacketData::IgnoreKeyRanges { ranges } => {
+        let (result_tx, result_rx) = oneshot::channel();
+        command_tx.send(Command::IgnoreKeyRanges { client, ranges, result_tx }).await?;
+        write_result(result_rx.await?, &mut *writer).await?;
+      },
+      ClientPacketData::AcceptKeyRanges { ranges } => {
+        let (result_tx, result_rx) = oneshot::channel();
+        command_tx.send(Command::AcceptKeyRanges { client, ranges, result_tx }).await?;
+        write_result(result_rx.await?, &mut *writer).await?;
+      },
+      ClientPacketData::EnterRawMode { driver } => {
+        let (result_tx, result_rx) = oneshot::channel();
+        command_tx.send(Command::EnterRawMode { client, driver, result_tx }).await?;
+        write_result(result_rx.await?, &mut *writer).await?;
+      },
+      ClientPacketData::LeaveRawMode => {
+        let (result_tx, result_rx) = oneshot::channel();
+        command_tx.send(Command::LeaveRawMode { client, result_tx }).await?;
+        write_result(result_rx.await?, &mut *writer).await?;
+      },
+      ClientPacketData::Packet { packet } => {
+        let (result_tx, result_rx) = oneshot::channel();
+        command_tx.send(Command::SendRawPacket { client, packet, result_tx }).await?;
+        report_write_result(result_rx.await?, &request_bytes, &mut *writer).await?;
+      },
+      ClientPacketData::SuspendDriver { driver } => {
+        let (result_tx, result_rx) = oneshot::channel();
+        command_tx.send(Command::SuspendDriver { client, driver, result_tx }).await?;
+        write_result(result_rx.await?, &mut *writer).await?;
+      },
+      ClientPacketData::ResumeDriver => {
+        let (result_tx, result_rx) = oneshot::channel();
+        command_tx.send(Command::ResumeDriver { client, result_tx }).await?;
+        write_result(result_rx.await?, &mut *writer).await?;
+      },
+      ClientPacketData::ParameterRequest { flags, parameter, sub_parameter } => {
+        let global = flags.contains(ParameterRequestFlags::Global);
+        let mut result = Ok(());
+        if flags.contains(ParameterRequestFlags::Subscribe) {
+          let (result_tx, result_rx) = oneshot::channel();
+          command_tx.send(Command::SubscribeParameter { client, parameter, sub_parameter, global, include_self: flags.contains(ParameterRequestFlags::IncludeSelf), result_tx }).await?;
+          result = result_rx.await?;
+        }
+        else if flags.contains(ParameterRequestFlags::Unsubscribe) {
+          let (result_tx, result_rx) = oneshot::channel();
+          command_tx.send(Command::UnsubscribeParameter { client, parameter, sub_parameter, global, result_tx }).await?;
+          result = result_rx.await?;
+        }
+        if result.is_ok() && flags.contains(ParameterRequestFlags::Get) {
+          let (result_tx, result_rx) = oneshot::channel();
+          command_tx.send(Command::GetParameter { client, parameter, sub_parameter, global, result_tx }).await?;
+          match result_rx.await? {
+            Ok(value) => {
+              let flags = if global {
+                ParameterValueFlags::Global
+              }
+              else {
+                ParameterValueFlags::empty()
+              };
+              write_packet(ServerPacket { data: ServerPacketData::ParameterValue { flags, parameter, sub_parameter, value }}, &mut *writer).await?;
+            },
+            Err(code) => write_packet(ServerPacket { data: ServerPacketData::Error { code }}, &mut *writer).await?,
+          }
+        }
+        else {
+          write_result(result, &mut *writer).await?;
+        }
+      },
+      ClientPacketData::ParameterValue { flags, parameter, sub_parameter, value } => {
+        if (parameter == Parameter::ComputerBrailleTable.into_u32() || parameter == Parameter::LiteraryBrailleTable.into_u32()) && !table_loads(&translator_tx, &String::from_utf8_lossy(&value)).await? {
+          write_packet(ServerPacket { data: ServerPacketData::Error { code: ErrorCode::InvalidParameter }}, &mut *writer).await?;
+          continue;
+        }
+        let (result_tx, result_rx) = oneshot::channel();
+        command_tx.send(Command::SetParameter { client, parameter, sub_parameter, global: flags.contains(ParameterValueFlags::Global), value, result_tx }).await?;
+        write_result(result_rx.await?, &mut *writer).await?;
       },
       ClientPacketData::GetDriverName => write_packet(ServerPacket { data: ServerPacketData::GetDriverName { driver: driver_name.clone().into() }}, &mut *writer).await?,
       ClientPacketData::GetModelId => write_packet(ServerPacket { data: ServerPacketData::GetModelId { model: model_id.clone().into() }}, &mut *writer).await?,
-      ClientPacketData::GetDisplaySize => write_packet(ServerPacket { data: ServerPacketData::GetDisplaySize { width: columns as u32, height: lines as u32 }}, &mut *writer).await?,
+      ClientPacketData::GetDisplaySize => {
+        let (columns, lines) = dimensions(&command_tx).await?;
+        write_packet(ServerPacket { data: ServerPacketData::GetDisplaySize { width: u32::from(columns), height: u32::from(lines) }}, &mut *writer).await?;
+      },
       _ => write_packet(ServerPacket { data: ServerPacketData::Ack }, &mut *writer).await?
     };
   }
 }
-pub async fn start(port: u16, auth_key: Option<String>, backend: ServerBackend) {
-  let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), port)).await.unwrap();
-  let (command_tx, command_rx) = mpsc::channel(32);
-  let (louis_tx, louis_rx) = mpsc::channel(32);
-  let louis_tx2 = louis_tx.clone();
-  thread::spawn(move || {
-    louis_runner(louis_rx)
-  });
-  tokio::spawn(async move {
-    handle_state(backend, command_rx, louis_tx2).await;
+enum Listener {
+  Tcp(TcpListener),
+  #[cfg(unix)]
+  Unix(UnixListener, PathBuf),
+}
+impl Listener {
+  fn local_addr(&self) -> Result<ListenAddress, std::io::Error> {
+    match self {
+      Listener::Tcp(listener) => Ok(ListenAddress::Tcp(listener.local_addr()?)),
+      #[cfg(unix)]
+      Listener::Unix(_, path) => Ok(ListenAddress::Local(path.clone())),
+    }
+  }
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddress {
+  Tcp(SocketAddr),
+  Local(PathBuf),
+}
+#[cfg(unix)]
+async fn bind(target: &BindTarget, unix_socket: &UnixSocketConfig) -> Result<Listener, std::io::Error> {
+  match target {
+    BindTarget::Local { number } => {
+      let path = unix_socket.directory.join(number.to_string());
+      Ok(Listener::Unix(bind_unix_socket(unix_socket, &path)?, path))
+    },
+    BindTarget::Path(path) => Ok(Listener::Unix(bind_unix_socket(unix_socket, path)?, path.clone())),
+    BindTarget::Tcp { host, port } => Ok(Listener::Tcp(TcpListener::bind((host.as_str(), *port)).await?)),
+  }
+}
+#[cfg(not(unix))]
+async fn bind(target: &BindTarget, _unix_socket: &UnixSocketConfig) -> Result<Listener, std::io::Error> {
+  match target {
+    BindTarget::Local { number } => Ok(Listener::Tcp(TcpListener::bind(("127.0.0.1", brlapi_types::host::BASE_PORT + number)).await?)),
+    BindTarget::Path(_) => Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "local sockets are not supported on this platform")),
+    BindTarget::Tcp { host, port } => Ok(Listener::Tcp(TcpListener::bind((host.as_str(), *port)).await?)),
+  }
+}
+#[derive(Clone)]
+struct ConnectionContext {
+  auth_failures: AuthFailures,
+  packet_timeout: Duration,
+  translator_tx: mpsc::Sender<TranslatorJob>,
+  command_tx: mpsc::Sender<Command>,
+  connections: Arc<Mutex<JoinSet<()>>>,
+}
+async fn spawn_connection<S: AsyncRead + AsyncWrite + Send + Unpin + 'static>(socket: S, peer: Peer, auth: AuthPolicy, context: &ConnectionContext) {
+  let mut connections = context.connections.lock().await;
+  while connections.try_join_next().is_some() {}
+  let context = context.clone();
+  connections.spawn(async move {
+    let _ = handle_connection(socket, peer, auth, context).await;
   });
+}
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+async fn accept_loop(listener: Listener, auth: AuthPolicy, context: ConnectionContext) {
   loop {
-    let (socket, _) = listener.accept().await.unwrap();
-    let auth_key2 = auth_key.clone();
-    let louis_tx2 = louis_tx.clone();
-    let command_tx2 = command_tx.clone();
-    tokio::spawn(async move {
-      let _ = handle_connection(socket, auth_key2, louis_tx2, command_tx2).await;
+    match &listener {
+      Listener::Tcp(listener) => {
+        let Ok((socket, address)) = listener.accept().await else {
+          sleep(ACCEPT_RETRY_DELAY).await;
+          continue;
+        };
+        spawn_connection(socket, Peer::Tcp(address), auth.clone(), &context).await;
+      },
+      #[cfg(unix)]
+      Listener::Unix(listener, _) => {
+        let Ok((socket, _)) = listener.accept().await else {
+          sleep(ACCEPT_RETRY_DELAY).await;
+          continue;
+        };
+        let credentials = socket.peer_cred().ok().map(|credentials| PeerCredentials { uid: credentials.uid(), gid: credentials.gid() });
+        spawn_connection(socket, Peer::Local(credentials), auth.clone(), &context).await;
+      }
+    }
+  }
+}
+pub struct Server {
+  listeners: Vec<(BindTarget, AuthPolicy)>,
+  unix_socket: UnixSocketConfig,
+  packet_timeout: Duration,
+  computer_braille_table: String,
+  literary_braille_table: String,
+  translator: Option<TranslatorFactory>,
+}
+impl Default for Server {
+  fn default() -> Self {
+    Server { listeners: Vec::new(), unix_socket: UnixSocketConfig::default(), packet_timeout: Duration::from_secs(10), computer_braille_table: "en-us-comp8.ctb".to_owned(), literary_braille_table: "en-ueb-g2.ctb".to_owned(), translator: default_translator() }
+  }
+}
+#[cfg(feature = "liblouis")]
+fn default_translator() -> Option<TranslatorFactory> {
+  Some(Box::new(|| Ok(Box::new(translator::LouisTranslator::new()?) as Box<dyn BrailleTranslator>)))
+}
+#[cfg(not(feature = "liblouis"))]
+fn default_translator() -> Option<TranslatorFactory> {
+  None
+}
+impl Server {
+  pub fn builder() -> Self {
+    Server::default()
+  }
+  pub fn listen(mut self, target: BindTarget, auth: AuthPolicy) -> Self {
+    self.listeners.push((target, auth));
+    self
+  }
+  pub fn unix_socket(mut self, config: UnixSocketConfig) -> Self {
+    self.unix_socket = config;
+    self
+  }
+  pub fn packet_timeout(mut self, packet_timeout: Duration) -> Self {
+    self.packet_timeout = packet_timeout;
+    self
+  }
+  pub fn computer_braille_table(mut self, table: impl Into<String>) -> Self {
+    self.computer_braille_table = table.into();
+    self
+  }
+  pub fn literary_braille_table(mut self, table: impl Into<String>) -> Self {
+    self.literary_braille_table = table.into();
+    self
+  }
+  pub fn translator<T: BrailleTranslator + 'static>(mut self, factory: impl FnOnce() -> Result<T, ServerError> + Send + 'static) -> Self {
+    self.translator = Some(Box::new(|| Ok(Box::new(factory()?) as Box<dyn BrailleTranslator>)));
+    self
+  }
+  pub async fn start(self, backend: ServerBackend) -> Result<ServerHandle, ServerError> {
+    let factory = self.translator.ok_or_else(|| ServerError::Translation("no braille translator was configured".to_owned()))?;
+    let (translator_tx, job_rx) = mpsc::channel(32);
+    let translator_tx2 = translator_tx.clone();
+    let (ready_tx, ready_rx) = oneshot::channel();
+    thread::spawn(move || {
+      translator_runner(factory, job_rx, ready_tx)
+    });
+    ready_rx.await.map_err(|_| ServerError::Translation("the translator thread exited".to_owned()))??;
+    for table in [&self.computer_braille_table, &self.literary_braille_table] {
+      if !table_loads(&translator_tx, table).await? {
+        return Err(ServerError::Translation(format!("could not load the braille table {table}")));
+      }
+    }
+    let mut listeners = Vec::new();
+    let mut local_addrs = Vec::new();
+    for (target, auth) in self.listeners {
+      let listener = bind(&target, &self.unix_socket).await?;
+      local_addrs.push(listener.local_addr()?);
+      listeners.push((listener, auth));
+    }
+    let (command_tx, command_rx) = mpsc::channel(32);
+    let (computer_braille_table, literary_braille_table) = (self.computer_braille_table, self.literary_braille_table);
+    let state_task = tokio::spawn(async move {
+      handle_state(backend, command_rx, translator_tx2, computer_braille_table, literary_braille_table).await
     });
+    let context = ConnectionContext { auth_failures: AuthFailures::default(), packet_timeout: self.packet_timeout, translator_tx, command_tx, connections: Arc::new(Mutex::new(JoinSet::new())) };
+    let accept_tasks = listeners.into_iter()
+      .map(|(listener, auth)| tokio::spawn(accept_loop(listener, auth, context.clone())))
+      .collect();
+    Ok(ServerHandle { local_addrs, accept_tasks, connections: context.connections, state_task: Some(state_task), shut_down: AtomicBool::new(false) })
+  }
+}
+pub struct ServerHandle {
+  local_addrs: Vec<ListenAddress>,
+  accept_tasks: Vec<JoinHandle<()>>,
+  connections: Arc<Mutex<JoinSet<()>>>,
+  state_task: Option<JoinHandle<Result<(), ServerError>>>,
+  shut_down: AtomicBool,
+}
+impl ServerHandle {
+  pub fn local_addrs(&self) -> &[ListenAddress] {
+    &self.local_addrs
+  }
+  pub fn shutdown(&self) {
+    for task in &self.accept_tasks {
+      task.abort();
+    }
+    if self.shut_down.swap(true, Ordering::SeqCst) {
+      return;
+    }
+    for address in &self.local_addrs {
+      if let ListenAddress::Local(path) = address {
+        let _ = std::fs::remove_file(path);
+      }
+    }
+  }
+  async fn stop_accepting(&mut self) {
+    self.shutdown();
+    for task in self.accept_tasks.drain(..) {
+      let _ = task.await;
+    }
+  }
+  pub async fn stopped(&mut self) -> Result<(), ServerError> {
+    let Some(state_task) = self.state_task.as_mut() else {
+      return Ok(());
+    };
+    let result = match state_task.await {
+      Ok(result) => result,
+      Err(error) if error.is_panic() => std::panic::resume_unwind(error.into_panic()),
+      Err(_) => Ok(()),
+    };
+    self.state_task = None;
+    result
+  }
+  pub async fn drain(&mut self) -> Result<(), ServerError> {
+    self.stop_accepting().await;
+    while self.connections.lock().await.join_next().await.is_some() {}
+    self.stopped().await
+  }
+  pub async fn close(&mut self) -> Result<(), ServerError> {
+    self.stop_accepting().await;
+    self.connections.lock().await.shutdown().await;
+    self.stopped().await
   }
 }
@@ -0,0 +1,154 @@
+use crate::tty::ClientId;
+use brlapi_types::ErrorCode;
+use brlapi_types::parameter::{Parameter, ParameterValue};
+use std::collections::HashMap;
+
+fn is_local(parameter: Parameter) -> bool {
+  matches!(parameter,
+    Parameter::ClientPriority
+    | Parameter::RetainDots
+    | Parameter::ComputerBrailleCellSize
+    | Parameter::LiteraryBraille
+    | Parameter::CursorDots
+    | Parameter::CursorBlinkPeriod
+    | Parameter::CursorBlinkPercentage
+    | Parameter::RenderedCells
+    | Parameter::ComputerBrailleTable
+    | Parameter::LiteraryBrailleTable)
+}
+fn lookup(parameter: u32, sub_parameter: u64) -> Result<Parameter, ErrorCode> {
+  Parameter::from_u32(parameter)
+    .filter(|parameter| sub_parameter == 0 || parameter.has_sub_parameter())
+    .ok_or(ErrorCode::InvalidParameter)
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Subscription {
+  parameter: u32,
+  sub_parameter: u64,
+  global: bool,
+  include_self: bool,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Scope {
+  Global,
+  Local(ClientId),
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ParameterUpdate {
+  pub(crate) client: ClientId,
+  pub(crate) global: bool,
+  pub(crate) value: Vec<u8>,
+}
+#[derive(Debug, Default)]
+pub(crate) struct ParameterStore {
+  global: HashMap<(u32, u64), Vec<u8>>,
+  local: HashMap<ClientId, HashMap<(u32, u64), Vec<u8>>>,
+  subscriptions: HashMap<ClientId, Vec<Subscription>>,
+}
+impl ParameterStore {
+  pub(crate) fn get(&self, client: ClientId, parameter: u32, sub_parameter: u64, global: bool) -> Result<Vec<u8>, ErrorCode> {
+    let typed_parameter = lookup(parameter, sub_parameter)?;
+    if !global && is_local(typed_parameter) && let Some(value) = self.local.get(&client).and_then(|values| values.get(&(parameter, sub_parameter))) {
+      return Ok(value.clone());
+    }
+    Ok(self.global.get(&(parameter, sub_parameter)).cloned().unwrap_or_else(|| typed_parameter.default_value().encode()))
+  }
+  pub(crate) fn value(&self, client: Option<ClientId>, parameter: Parameter) -> ParameterValue {
+    let key = (parameter.into_u32(), 0);
+    client.and_then(|client| self.local.get(&client))
+      .and_then(|values| values.get(&key))
+      .or_else(|| self.global.get(&key))
+      .and_then(|value| ParameterValue::decode(parameter, value).ok())
+      .unwrap_or_else(|| parameter.default_value())
+  }
+  pub(crate) fn set(&mut self, client: ClientId, parameter: u32, sub_parameter: u64, global: bool, value: Vec<u8>) -> Result<Scope, ErrorCode> {
+    let typed_parameter = lookup(parameter, sub_parameter)?;
+    if typed_parameter.is_read_only() {
+      return Err(ErrorCode::ReadOnlyParameter);
+    }
+    ParameterValue::decode(typed_parameter, &value).map_err(|_| ErrorCode::InvalidParameter)?;
+    if global || !is_local(typed_parameter) {
+      self.global.insert((parameter, sub_parameter), value);
+      Ok(Scope::Global)
+    }
+    else {
+      self.local.entry(client).or_default().insert((parameter, sub_parameter), value);
+      Ok(Scope::Local(client))
+    }
+  }
+  pub(crate) fn set_global(&mut self, sub_parameter: u64, value: &ParameterValue) -> bool {
+    let key = (value.parameter().into_u32(), sub_parameter);
+    let value = value.encode();
+    self.global.insert(key, value.clone()).as_ref() != Some(&value)
+  }
+  pub(crate) fn subscribe(&mut self, client: ClientId, parameter: u32, sub_parameter: u64, global: bool, include_self: bool) -> Result<(), ErrorCode> {
+    lookup(parameter, sub_parameter)?;
+    let subscriptions = self.subscriptions.entry(client).or_default();
+    subscriptions.retain(|subscription| (subscription.parameter, subscription.sub_parameter, subscription.global) != (parameter, sub_parameter, global));
+    subscriptions.push(Subscription { parameter, sub_parameter, global, include_self });
+    Ok(())
+  }
+  pub(crate) fn unsubscribe(&mut self, client: ClientId, parameter: u32, sub_parameter: u64, global: bool) -> Result<(), ErrorCode> {
+    let subscriptions = self.subscriptions.get_mut(&client).ok_or(ErrorCode::InvalidParameter)?;
+    let count = subscriptions.len();
+    subscriptions.retain(|subscription| (subscription.parameter, subscription.sub_parameter, subscription.global) != (parameter, sub_parameter, global));
+    if subscriptions.len() == count {
+      return Err(ErrorCode::InvalidParameter);
+    }
+    Ok(())
+  }
+  pub(crate) fn updates(&self, origin: Option<ClientId>, parameter: u32, sub_parameter: u64, scope: Scope) -> Vec<ParameterUpdate> {
+    let mut updates = Vec::new();
+    for (client, subscriptions) in &self.subscriptions {
+      for subscription in subscriptions.iter().filter(|subscription| subscription.parameter == parameter && subscription.sub_parameter == sub_parameter) {
+        if origin == Some(*client) && !subscription.include_self {
+          continue;
+        }
+        let overridden = self.local.get(client).is_some_and(|values| values.contains_key(&(parameter, sub_parameter)));
+        let affected = match scope {
+          Scope::Global => subscription.global || !overridden,
+          Scope::Local(owner) => owner == *client && !subscription.global,
+        };
+        if affected && let Ok(value) = self.get(*client, parameter, sub_parameter, subscription.global) {
+          updates.push(ParameterUpdate { client: *client, global: subscription.global, value });
+        }
+      }
+    }
+    updates
+  }
+  pub(crate) fn remove_client(&mut self, client: ClientId) {
+    self.local.remove(&client);
+    self.subscriptions.remove(&client);
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn updates_follow_subscription_scope() {
+    let mut parameters = ParameterStore::default();
+    let parameter = Parameter::LiteraryBraille.into_u32();
+    parameters.subscribe(1, parameter, 0, false, false).unwrap();
+    parameters.subscribe(2, parameter, 0, true, false).unwrap();
+    let scope = parameters.set(3, parameter, 0, true, ParameterValue::LiteraryBraille(true).encode()).unwrap();
+    let mut updates = parameters.updates(Some(3), parameter, 0, scope);
+    updates.sort_by_key(|update| update.client);
+    let value = ParameterValue::LiteraryBraille(true).encode();
+    assert_eq!(updates, vec![
+      ParameterUpdate { client: 1, global: false, value: value.clone() },
+      ParameterUpdate { client: 2, global: true, value },
+    ]);
+  }
+  #[test]
+  fn sub_parameters_only_where_supported() {
+    let mut parameters = ParameterStore::default();
+    let clipboard = Parameter::ClipboardContent.into_u32();
+    let value = ParameterValue::ClipboardContent("text".to_owned()).encode();
+    assert_eq!(parameters.set(1, clipboard, 1, true, value.clone()), Err(ErrorCode::InvalidParameter));
+    assert_eq!(parameters.set(1, clipboard, 0, true, value), Ok(Scope::Global));
+    assert_eq!(parameters.subscribe(1, clipboard, 1, true, false), Err(ErrorCode::InvalidParameter));
+    assert_eq!(parameters.get(1, Parameter::DisplaySize.into_u32(), 1, true), Err(ErrorCode::InvalidParameter));
+    assert!(parameters.get(1, Parameter::DriverKeycodeName.into_u32(), 1, true).is_ok());
+  }
+}
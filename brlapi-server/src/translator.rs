@@ -0,0 +1,163 @@
+use crate::error::ServerError;
+#[cfg(feature = "liblouis")]
+use louis::Louis;
+#[cfg(feature = "liblouis")]
+use std::ffi::CString;
+#[cfg(feature = "liblouis")]
+use louis_sys::{formtype, lou_backTranslate, lou_translate, widechar};
+#[cfg(feature = "liblouis")]
+use std::os::raw::{c_char, c_int};
+#[cfg(feature = "liblouis")]
+use std::ptr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Translation {
+  pub cells: Vec<u8>,
+  pub positions: Vec<usize>,
+  pub input_positions: Vec<usize>
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackTranslation {
+  pub text: String,
+  pub positions: Vec<usize>,
+  pub input_positions: Vec<usize>
+}
+/// Translates between text and braille cells, keeping track of which input produced which output.
+///
+/// `Translation::positions` has `text.chars().count() + 1` entries: entry `i` is the first cell produced
+/// for character `i`, the entries never decrease, and the last one is `cells.len()`.
+/// `Translation::input_positions` has `cells.len()` entries: entry `i` is the character that produced cell `i`.
+///
+/// `BackTranslation` follows the same contract with the roles swapped: `positions` has `cells.len() + 1`
+/// entries indexing characters of `text` and ending at `text.chars().count()`, and `input_positions` has one
+/// cell index per character of `text`.
+pub trait BrailleTranslator {
+  fn translate(&self, tables: &str, text: &str) -> Result<Translation, ServerError>;
+  fn back_translate(&self, tables: &str, cells: &[u8]) -> Result<BackTranslation, ServerError>;
+}
+pub type TranslatorFactory = Box<dyn FnOnce() -> Result<Box<dyn BrailleTranslator>, ServerError> + Send>;
+pub(crate) fn braille_pattern(char: char) -> Option<u8> {
+  u32::from(char).checked_sub(10240).and_then(|dots| u8::try_from(dots).ok())
+}
+pub(crate) fn braille_string(cells: &[u8]) -> String {
+  cells.iter().filter_map(|dots| char::from_u32(10240 + u32::from(*dots))).collect()
+}
+#[cfg(feature = "liblouis")]
+type LouisFunction = unsafe extern "C" fn(*const c_char, *const widechar, *mut c_int, *mut widechar, *mut c_int, *mut formtype, *mut c_char, *mut c_int, *mut c_int, *mut c_int, c_int) -> c_int;
+#[cfg(feature = "liblouis")]
+struct LouisOutput {
+  text: Vec<char>,
+  output_positions: Vec<usize>,
+  input_positions: Vec<usize>
+}
+#[cfg(feature = "liblouis")]
+fn monotonic_positions(positions: Vec<usize>, end: usize) -> Vec<usize> {
+  let mut previous = 0;
+  let mut monotonic: Vec<usize> = positions.into_iter().map(|position| {
+    previous = position.clamp(previous, end);
+    previous
+  }).collect();
+  monotonic.push(end);
+  monotonic
+}
+#[cfg(feature = "liblouis")]
+pub struct LouisTranslator {
+  _louis: Louis
+}
+#[cfg(feature = "liblouis")]
+impl LouisTranslator {
+  pub fn new() -> Result<Self, ServerError> {
+    let louis = Louis::new().map_err(|error| ServerError::Translation(format!("{error:?}")))?;
+    Ok(LouisTranslator { _louis: louis })
+  }
+  #[allow(clippy::unnecessary_cast, clippy::unnecessary_fallible_conversions, clippy::useless_conversion)]
+  fn run(function: LouisFunction, tables: &str, input: &[char]) -> Result<LouisOutput, ServerError> {
+    let utf16 = size_of::<widechar>() == 2;
+    let error = || ServerError::Translation(format!("liblouis could not translate with {tables}"));
+    let table_list = CString::new(tables).map_err(|_| error())?;
+    let mut units: Vec<widechar> = Vec::with_capacity(input.len());
+    let mut unit_chars = Vec::with_capacity(input.len());
+    let mut char_units = Vec::with_capacity(input.len());
+    for (index, char) in input.iter().enumerate() {
+      char_units.push(units.len());
+      let mut buffer = [0; 2];
+      let encoded: Vec<u32> = if utf16 {
+        char.encode_utf16(&mut buffer).iter().map(|unit| u32::from(*unit)).collect()
+      }
+      else {
+        vec![u32::from(*char)]
+      };
+      for unit in encoded {
+        units.push(widechar::try_from(unit).map_err(|_| error())?);
+        unit_chars.push(index);
+      }
+    }
+    let mut capacity = (units.len()*4).max(16);
+    loop {
+      let mut inlen = c_int::try_from(units.len()).map_err(|_| error())?;
+      let mut outlen = c_int::try_from(capacity).map_err(|_| error())?;
+      let mut outbuf: Vec<widechar> = vec![0; capacity];
+      let mut output_pos: Vec<c_int> = vec![0; units.len()];
+      let mut input_pos: Vec<c_int> = vec![0; capacity];
+      let result = unsafe {
+        function(table_list.as_ptr(), units.as_ptr(), &raw mut inlen, outbuf.as_mut_ptr(), &raw mut outlen, ptr::null_mut(), ptr::null_mut(), output_pos.as_mut_ptr(), input_pos.as_mut_ptr(), ptr::null_mut(), ::louis::modes::DOTS_UNICODE as c_int)
+      };
+      if result == 0 {
+        return Err(error());
+      }
+      let consumed = usize::try_from(inlen).unwrap_or(0).min(units.len());
+      let produced = usize::try_from(outlen).unwrap_or(0).min(capacity);
+      if consumed < units.len() && produced == capacity {
+        capacity *= 2;
+        continue;
+      }
+      let output: Vec<u32> = outbuf[..produced].iter().map(|unit| u32::from(*unit)).collect();
+      let mut text = Vec::with_capacity(produced);
+      let mut output_units = Vec::with_capacity(produced);
+      let mut output_chars = Vec::with_capacity(produced);
+      if utf16 {
+        let mut unit = 0;
+        for char in char::decode_utf16(output.iter().map(|unit| *unit as u16)) {
+          let char = char.unwrap_or(char::REPLACEMENT_CHARACTER);
+          output_units.push(unit);
+          let width = if char == char::REPLACEMENT_CHARACTER { 1 } else { char.len_utf16() };
+          output_chars.extend(std::iter::repeat_n(text.len(), width));
+          unit += width;
+          text.push(char);
+        }
+      }
+      else {
+        for (unit, value) in output.iter().enumerate() {
+          output_units.push(unit);
+          output_chars.push(text.len());
+          text.push(char::from_u32(*value).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+      }
+      let output_positions = char_units.iter()
+        .map(|unit| usize::try_from(output_pos[*unit]).ok().filter(|_| *unit < consumed).and_then(|unit| output_chars.get(unit).copied()).unwrap_or(text.len()))
+        .collect();
+      let input_positions = output_units.iter()
+        .map(|unit| usize::try_from(input_pos[*unit]).ok().and_then(|unit| unit_chars.get(unit).copied()).unwrap_or(input.len().saturating_sub(1)))
+        .collect();
+      return Ok(LouisOutput { text, output_positions, input_positions });
+    }
+  }
+}
+#[cfg(feature = "liblouis")]
+impl BrailleTranslator for LouisTranslator {
+  fn translate(&self, tables: &str, text: &str) -> Result<Translation, ServerError> {
+    let characters: Vec<char> = text.chars().collect();
+    let output = Self::run(lou_translate, tables, &characters)?;
+    let cells = output.text.iter()
+      .map(|char| braille_pattern(*char).ok_or_else(|| ServerError::Translation(format!("{char:?} is not a braille pattern"))))
+      .collect::<Result<Vec<u8>, ServerError>>()?;
+    let positions = monotonic_positions(output.output_positions, cells.len());
+    Ok(Translation { cells, positions, input_positions: output.input_positions })
+  }
+  fn back_translate(&self, tables: &str, cells: &[u8]) -> Result<BackTranslation, ServerError> {
+    let patterns: Vec<char> = braille_string(cells).chars().collect();
+    let output = Self::run(lou_backTranslate, tables, &patterns)?;
+    let positions = monotonic_positions(output.output_positions, output.text.len());
+    Ok(BackTranslation { text: output.text.into_iter().collect(), positions, input_positions: output.input_positions })
+  }
+}
@@ -0,0 +1,163 @@
+use brlapi_types::ErrorCode;
+use std::collections::{BTreeMap, HashMap};
+
+pub(crate) type ClientId = u64;
+#[derive(Debug, Default)]
+struct TtyNode {
+  focus: Option<u32>,
+  focus_history: Vec<u32>,
+  clients: Vec<ClientId>,
+  children: BTreeMap<u32, TtyNode>,
+}
+impl TtyNode {
+  fn is_empty(&self) -> bool {
+    self.clients.is_empty() && self.children.is_empty()
+  }
+  fn focus_on(&mut self, tty: u32) {
+    self.focus_history.retain(|previous| *previous != tty);
+    self.focus_history.push(tty);
+    self.focus = Some(tty);
+  }
+  fn add_child(&mut self, tty: u32) -> &mut TtyNode {
+    if self.focus.is_none() {
+      self.focus_on(tty);
+    }
+    else if !self.focus_history.contains(&tty) {
+      let index = self.focus_history.len().saturating_sub(1);
+      self.focus_history.insert(index, tty);
+    }
+    self.children.entry(tty).or_default()
+  }
+  fn remove(&mut self, client: ClientId, path: &[u32]) {
+    match path.split_first() {
+      None => self.clients.retain(|owner| *owner != client),
+      Some((tty, rest)) => {
+        if let Some(child) = self.children.get_mut(tty) {
+          child.remove(client, rest);
+          if child.is_empty() {
+            self.children.remove(tty);
+            self.focus_history.retain(|previous| previous != tty);
+            if self.focus == Some(*tty) {
+              self.focus = self.focus_history.last().copied();
+            }
+          }
+        }
+      }
+    }
+  }
+}
+#[derive(Debug, Default)]
+pub(crate) struct TtyTree {
+  root: TtyNode,
+  paths: HashMap<ClientId, Vec<u32>>,
+}
+impl TtyTree {
+  pub(crate) fn enter(&mut self, client: ClientId, path: Vec<u32>) -> Result<(), ErrorCode> {
+    if self.paths.contains_key(&client) {
+      return Err(ErrorCode::IllegalInstruction);
+    }
+    let mut node = &mut self.root;
+    for tty in &path {
+      node = node.add_child(*tty);
+    }
+    node.clients.push(client);
+    self.paths.insert(client, path);
+    Ok(())
+  }
+  pub(crate) fn leave(&mut self, client: ClientId) -> Result<(), ErrorCode> {
+    let path = self.paths.remove(&client).ok_or(ErrorCode::IllegalInstruction)?;
+    self.root.remove(client, &path);
+    Ok(())
+  }
+  pub(crate) fn set_focus(&mut self, client: ClientId, tty: u32) -> Result<(), ErrorCode> {
+    let path = self.paths.get(&client).ok_or(ErrorCode::IllegalInstruction)?;
+    let mut node = &mut self.root;
+    for tty in path {
+      node = node.children.get_mut(tty).ok_or(ErrorCode::UnknownTty)?;
+    }
+    node.focus_on(tty);
+    Ok(())
+  }
+  pub(crate) fn set_root_focus(&mut self, tty: u32) {
+    self.root.focus_on(tty);
+  }
+  pub(crate) fn is_in_tty_mode(&self, client: ClientId) -> bool {
+    self.paths.contains_key(&client)
+  }
+  pub(crate) fn focused_client(&self) -> Option<ClientId> {
+    self.focus_chain().first().copied()
+  }
+  pub(crate) fn focus_chain(&self) -> Vec<ClientId> {
+    let mut node = &self.root;
+    let mut nodes = vec![node];
+    while let Some(child) = node.focus.and_then(|tty| node.children.get(&tty)) {
+      node = child;
+      nodes.push(node);
+    }
+    nodes.iter().rev().flat_map(|node| node.clients.iter().rev().copied()).collect()
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn first_tty_gets_focus() {
+    let mut ttys = TtyTree::default();
+    ttys.enter(1, vec![1]).unwrap();
+    ttys.enter(2, vec![2]).unwrap();
+    assert_eq!(ttys.focused_client(), Some(1));
+    assert_eq!(ttys.enter(1, vec![3]), Err(ErrorCode::IllegalInstruction));
+  }
+  #[test]
+  fn focus_returns_when_focused_tty_is_left() {
+    let mut ttys = TtyTree::default();
+    ttys.enter(1, vec![1]).unwrap();
+    ttys.enter(2, vec![2]).unwrap();
+    ttys.leave(1).unwrap();
+    assert_eq!(ttys.focused_client(), Some(2));
+    ttys.enter(1, vec![1]).unwrap();
+    assert_eq!(ttys.focused_client(), Some(2));
+  }
+  #[test]
+  fn focus_returns_to_previous_owner() {
+    let mut ttys = TtyTree::default();
+    ttys.enter(1, vec![1]).unwrap();
+    ttys.enter(2, vec![2]).unwrap();
+    ttys.enter(3, vec![3]).unwrap();
+    ttys.set_root_focus(2);
+    ttys.set_root_focus(3);
+    assert_eq!(ttys.focused_client(), Some(3));
+    ttys.leave(3).unwrap();
+    assert_eq!(ttys.focused_client(), Some(2));
+    ttys.leave(2).unwrap();
+    assert_eq!(ttys.focused_client(), Some(1));
+    ttys.leave(1).unwrap();
+    assert_eq!(ttys.focused_client(), None);
+  }
+  #[test]
+  fn root_focus_follows_active_tty() {
+    let mut ttys = TtyTree::default();
+    ttys.enter(1, vec![1]).unwrap();
+    ttys.enter(2, vec![2]).unwrap();
+    ttys.set_root_focus(2);
+    assert_eq!(ttys.focused_client(), Some(2));
+    ttys.set_root_focus(7);
+    assert_eq!(ttys.focused_client(), None);
+    ttys.enter(3, vec![7]).unwrap();
+    assert_eq!(ttys.focused_client(), Some(3));
+  }
+  #[test]
+  fn nested_focus_chain() {
+    let mut ttys = TtyTree::default();
+    ttys.enter(1, vec![1]).unwrap();
+    ttys.enter(2, vec![1, 5]).unwrap();
+    ttys.enter(3, vec![1, 6]).unwrap();
+    assert_eq!(ttys.focus_chain(), vec![2, 1]);
+    ttys.set_focus(1, 6).unwrap();
+    assert_eq!(ttys.focus_chain(), vec![3, 1]);
+    assert_eq!(ttys.set_focus(4, 6), Err(ErrorCode::IllegalInstruction));
+    ttys.leave(3).unwrap();
+    assert_eq!(ttys.focus_chain(), vec![2, 1]);
+  }
+}
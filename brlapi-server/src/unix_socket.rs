@@ -0,0 +1,54 @@
+#[cfg(unix)]
+use std::fs;
+#[cfg(unix)]
+use std::io::ErrorKind;
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream as StdUnixStream;
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+pub struct UnixSocketConfig {
+  pub directory: PathBuf,
+  pub directory_permissions: u32,
+  pub socket_permissions: u32,
+  pub remove_stale_socket: bool,
+}
+impl Default for UnixSocketConfig {
+  fn default() -> Self {
+    UnixSocketConfig { directory: PathBuf::from("/var/lib/BrlAPI"), directory_permissions: 0o1777, socket_permissions: 0o666, remove_stale_socket: true }
+  }
+}
+#[cfg(unix)]
+fn remove_stale_socket(path: &Path) -> Result<(), std::io::Error> {
+  let metadata = match fs::symlink_metadata(path) {
+    Ok(metadata) => metadata,
+    Err(error) if error.kind() == ErrorKind::NotFound => return Ok(()),
+    Err(error) => return Err(error),
+  };
+  if !metadata.file_type().is_socket() {
+    return Err(std::io::Error::new(ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path.display())));
+  }
+  match StdUnixStream::connect(path) {
+    Ok(_) => Err(std::io::Error::new(ErrorKind::AddrInUse, format!("{} is in use by another server", path.display()))),
+    Err(error) if error.kind() == ErrorKind::ConnectionRefused => fs::remove_file(path),
+    Err(error) => Err(error),
+  }
+}
+#[cfg(unix)]
+pub fn bind_unix_socket(config: &UnixSocketConfig, path: &Path) -> Result<UnixListener, std::io::Error> {
+  if let Some(directory) = path.parent() && !directory.exists() {
+    fs::DirBuilder::new().recursive(true).mode(config.directory_permissions).create(directory)?;
+    fs::set_permissions(directory, fs::Permissions::from_mode(config.directory_permissions))?;
+  }
+  if config.remove_stale_socket {
+    remove_stale_socket(path)?;
+  }
+  let listener = UnixListener::bind(path)?;
+  fs::set_permissions(path, fs::Permissions::from_mode(config.socket_permissions))?;
+  Ok(listener)
+}
@@ -0,0 +1,110 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::str::FromStr;
+pub const BASE_PORT: u16 = 4101;
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum HostParseError {
+  InvalidNumber(String),
+  PortOutOfRange(u16),
+  UnterminatedBracket,
+  TrailingCharacters(String),
+}
+impl fmt::Display for HostParseError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      HostParseError::InvalidNumber(number) => write!(f, "invalid host number: {number}"),
+      HostParseError::PortOutOfRange(number) => write!(f, "host number {number} is out of range"),
+      HostParseError::UnterminatedBracket => write!(f, "unterminated IPv6 address bracket"),
+      HostParseError::TrailingCharacters(rest) => write!(f, "unexpected characters after IPv6 address: {rest}"),
+    }
+  }
+}
+impl std::error::Error for HostParseError {}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum BindTarget {
+  Local { number: u16 },
+  Path(PathBuf),
+  Tcp { host: String, port: u16 },
+}
+fn parse_number(number: &str) -> Result<u16, HostParseError> {
+  if number.is_empty() {
+    return Ok(0);
+  }
+  number.parse().map_err(|_| HostParseError::InvalidNumber(number.to_owned()))
+}
+fn tcp(host: &str, number: u16) -> Result<BindTarget, HostParseError> {
+  let port = BASE_PORT.checked_add(number).ok_or(HostParseError::PortOutOfRange(number))?;
+  Ok(BindTarget::Tcp { host: host.to_owned(), port })
+}
+impl BindTarget {
+  pub fn parse_list(hosts: &str) -> Result<Vec<BindTarget>, HostParseError> {
+    hosts.split('+').map(str::parse).collect()
+  }
+}
+impl FromStr for BindTarget {
+  type Err = HostParseError;
+  fn from_str(host: &str) -> Result<Self, Self::Err> {
+    if host.starts_with('/') {
+      return Ok(BindTarget::Path(PathBuf::from(host)));
+    }
+    if let Some(rest) = host.strip_prefix('[') {
+      let (address, rest) = rest.split_once(']').ok_or(HostParseError::UnterminatedBracket)?;
+      return match rest {
+        "" => tcp(address, 0),
+        _ => match rest.strip_prefix(':') {
+          Some(number) => tcp(address, parse_number(number)?),
+          None => Err(HostParseError::TrailingCharacters(rest.to_owned())),
+        },
+      };
+    }
+    if host.matches(':').count() > 1 {
+      return tcp(host, 0);
+    }
+    match host.split_once(':') {
+      Some(("", number)) => Ok(BindTarget::Local { number: parse_number(number)? }),
+      Some((address, number)) => tcp(address, parse_number(number)?),
+      None if host.is_empty() => Ok(BindTarget::Local { number: 0 }),
+      None => tcp(host, 0),
+    }
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(host: &str) -> Result<BindTarget, HostParseError> {
+    host.parse()
+  }
+  fn tcp_target(host: &str, port: u16) -> BindTarget {
+    BindTarget::Tcp { host: host.to_owned(), port }
+  }
+  #[test]
+  fn local_sockets() {
+    assert_eq!(parse(":0"), Ok(BindTarget::Local { number: 0 }));
+    assert_eq!(parse(":1"), Ok(BindTarget::Local { number: 1 }));
+    assert_eq!(parse(":"), Ok(BindTarget::Local { number: 0 }));
+    assert_eq!(parse(""), Ok(BindTarget::Local { number: 0 }));
+    assert_eq!(parse("/run/brlapi/socket"), Ok(BindTarget::Path(PathBuf::from("/run/brlapi/socket"))));
+  }
+  #[test]
+  fn tcp_hosts() {
+    assert_eq!(parse("localhost"), Ok(tcp_target("localhost", BASE_PORT)));
+    assert_eq!(parse("localhost:3"), Ok(tcp_target("localhost", BASE_PORT + 3)));
+    assert_eq!(parse("0.0.0.0:0"), Ok(tcp_target("0.0.0.0", BASE_PORT)));
+    assert_eq!(parse("[::1]:2"), Ok(tcp_target("::1", BASE_PORT + 2)));
+    assert_eq!(parse("[::1]"), Ok(tcp_target("::1", BASE_PORT)));
+    assert_eq!(parse("::1"), Ok(tcp_target("::1", BASE_PORT)));
+  }
+  #[test]
+  fn invalid_hosts() {
+    assert_eq!(parse("localhost:65535"), Err(HostParseError::PortOutOfRange(65535)));
+    assert_eq!(parse("localhost:70000"), Err(HostParseError::InvalidNumber("70000".to_owned())));
+    assert_eq!(parse(":x"), Err(HostParseError::InvalidNumber("x".to_owned())));
+    assert_eq!(parse("[::1"), Err(HostParseError::UnterminatedBracket));
+    assert_eq!(parse("[::1]x"), Err(HostParseError::TrailingCharacters("x".to_owned())));
+  }
+  #[test]
+  fn host_lists() {
+    assert_eq!(BindTarget::parse_list(":0+localhost:1"), Ok(vec![BindTarget::Local { number: 0 }, tcp_target("localhost", BASE_PORT + 1)]));
+  }
+}
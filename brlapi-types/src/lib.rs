@@ -4,10 +4,14 @@
 #![allow(clippy::ref_option)]
 #![allow(clippy::trivially_copy_pass_by_ref)]
 #![allow(clippy::match_same_arms)]
+#![allow(clippy::missing_errors_doc)]
+pub mod host;
 pub mod keycode;
+pub mod parameter;
 use crate::keycode::Keycode;
 use binrw::{NullString, binrw};
 use bitflags::bitflags;
+pub const MAX_PACKET_SIZE: usize = 4096;
 #[binrw]
 #[brw(big, repr(u32))]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
@@ -216,11 +220,9 @@ pub enum ClientPacketData {
   #[br(pre_assert(ty == PacketType::Version))]
   Version { version: u32 },
   #[br(pre_assert(ty == PacketType::Auth))]
-  #[br(assert(auth_type == AuthType::Key))]
-  #[bw(assert(*auth_type == AuthType::Key))]
   Auth {
     auth_type: AuthType,
-    #[br(count(size-4))]
+    #[br(count(size.saturating_sub(4)))]
     key: Vec<u8>,
   },
   #[br(pre_assert(ty == PacketType::GetDriverName))]
@@ -295,7 +297,7 @@ pub enum ClientPacketData {
     flags: ParameterValueFlags,
     parameter: u32,
     sub_parameter: u64,
-    #[br(count(size-16))]
+    #[br(count(size.saturating_sub(16)))]
     value: Vec<u8>,
   },
 }
@@ -422,7 +424,7 @@ pub enum ServerPacketData {
   #[br(pre_assert(ty == PacketType::Exception))]
   Exception {
     code: ErrorCode,
-    #[br(count(size-4))]
+    #[br(count(size.saturating_sub(4)))]
     packet: Vec<u8>,
   },
   #[br(pre_assert(ty == PacketType::Key))]
@@ -456,7 +458,7 @@ pub enum ServerPacketData {
     flags: ParameterValueFlags,
     parameter: u32,
     sub_parameter: u64,
-    #[br(count(size-16))]
+    #[br(count(size.saturating_sub(16)))]
     value: Vec<u8>,
   },
   #[br(pre_assert(ty == PacketType::ParameterUpdate))]
@@ -466,7 +468,7 @@ pub enum ServerPacketData {
     flags: ParameterValueFlags,
     parameter: u32,
     sub_parameter: u64,
-    #[br(count(size-16))]
+    #[br(count(size.saturating_sub(16)))]
     value: Vec<u8>,
   },
 }
@@ -560,3 +562,25 @@ pub struct ServerPacket {
   #[br(args(size, ty))]
   pub data: ServerPacketData,
 }
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use binrw::BinRead;
+  use std::io::Cursor;
+
+  #[test]
+  fn undersized_packets() {
+    let mut auth = vec![0, 0, 0, 2];
+    auth.extend_from_slice(b"\0\0\0a");
+    auth.extend_from_slice(&[0; 8]);
+    assert!(ClientPacket::read(&mut Cursor::new(&auth)).is_err());
+    let mut value = vec![0, 0, 0, 8];
+    value.extend_from_slice(b"\0\0PV");
+    value.extend_from_slice(&[0; 24]);
+    assert!(ClientPacket::read(&mut Cursor::new(&value)).is_err());
+    let mut exception = vec![0, 0, 0, 2];
+    exception.extend_from_slice(b"\0\0\0E");
+    exception.extend_from_slice(&[0; 8]);
+    assert!(ServerPacket::read(&mut Cursor::new(&exception)).is_err());
+  }
+}
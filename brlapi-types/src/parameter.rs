@@ -0,0 +1,427 @@
+use std::fmt;
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+#[repr(u32)]
+pub enum Parameter {
+  ServerVersion = 0,
+  ClientPriority = 1,
+  DriverName = 2,
+  DriverCode = 3,
+  DriverVersion = 4,
+  DeviceModel = 5,
+  DeviceCellSize = 31,
+  DisplaySize = 6,
+  DeviceIdentifier = 7,
+  DeviceSpeed = 8,
+  DeviceOnline = 9,
+  RetainDots = 10,
+  ComputerBrailleCellSize = 11,
+  LiteraryBraille = 12,
+  CursorDots = 13,
+  CursorBlinkPeriod = 14,
+  CursorBlinkPercentage = 15,
+  RenderedCells = 16,
+  SkipIdenticalLines = 17,
+  AudibleAlerts = 18,
+  ClipboardContent = 19,
+  BoundCommandKeycodes = 20,
+  CommandKeycodeName = 21,
+  CommandKeycodeSummary = 22,
+  DefinedDriverKeycodes = 23,
+  DriverKeycodeName = 24,
+  DriverKeycodeSummary = 25,
+  ComputerBrailleRowsMask = 26,
+  ComputerBrailleRowCells = 27,
+  ComputerBrailleTable = 28,
+  LiteraryBrailleTable = 29,
+  MessageLocale = 30,
+}
+impl Parameter {
+  #[must_use]
+  pub const fn from_u32(value: u32) -> Option<Self> {
+    Some(match value {
+      0 => Parameter::ServerVersion,
+      1 => Parameter::ClientPriority,
+      2 => Parameter::DriverName,
+      3 => Parameter::DriverCode,
+      4 => Parameter::DriverVersion,
+      5 => Parameter::DeviceModel,
+      6 => Parameter::DisplaySize,
+      7 => Parameter::DeviceIdentifier,
+      8 => Parameter::DeviceSpeed,
+      9 => Parameter::DeviceOnline,
+      10 => Parameter::RetainDots,
+      11 => Parameter::ComputerBrailleCellSize,
+      12 => Parameter::LiteraryBraille,
+      13 => Parameter::CursorDots,
+      14 => Parameter::CursorBlinkPeriod,
+      15 => Parameter::CursorBlinkPercentage,
+      16 => Parameter::RenderedCells,
+      17 => Parameter::SkipIdenticalLines,
+      18 => Parameter::AudibleAlerts,
+      19 => Parameter::ClipboardContent,
+      20 => Parameter::BoundCommandKeycodes,
+      21 => Parameter::CommandKeycodeName,
+      22 => Parameter::CommandKeycodeSummary,
+      23 => Parameter::DefinedDriverKeycodes,
+      24 => Parameter::DriverKeycodeName,
+      25 => Parameter::DriverKeycodeSummary,
+      26 => Parameter::ComputerBrailleRowsMask,
+      27 => Parameter::ComputerBrailleRowCells,
+      28 => Parameter::ComputerBrailleTable,
+      29 => Parameter::LiteraryBrailleTable,
+      30 => Parameter::MessageLocale,
+      31 => Parameter::DeviceCellSize,
+      _ => return None,
+    })
+  }
+  #[must_use]
+  pub const fn into_u32(self) -> u32 {
+    self as _
+  }
+  #[must_use]
+  pub const fn is_read_only(self) -> bool {
+    matches!(self,
+      Parameter::ServerVersion
+      | Parameter::DriverName
+      | Parameter::DriverCode
+      | Parameter::DriverVersion
+      | Parameter::DeviceModel
+      | Parameter::DeviceCellSize
+      | Parameter::DisplaySize
+      | Parameter::DeviceIdentifier
+      | Parameter::DeviceSpeed
+      | Parameter::DeviceOnline
+      | Parameter::RenderedCells
+      | Parameter::BoundCommandKeycodes
+      | Parameter::CommandKeycodeName
+      | Parameter::CommandKeycodeSummary
+      | Parameter::DefinedDriverKeycodes
+      | Parameter::DriverKeycodeName
+      | Parameter::DriverKeycodeSummary
+      | Parameter::ComputerBrailleRowsMask
+      | Parameter::ComputerBrailleRowCells)
+  }
+  #[must_use]
+  pub const fn has_sub_parameter(self) -> bool {
+    matches!(self,
+      Parameter::CommandKeycodeName
+      | Parameter::CommandKeycodeSummary
+      | Parameter::DriverKeycodeName
+      | Parameter::DriverKeycodeSummary
+      | Parameter::ComputerBrailleRowCells)
+  }
+  #[must_use]
+  pub fn default_value(self) -> ParameterValue {
+    match self {
+      Parameter::ServerVersion => ParameterValue::ServerVersion(0),
+      Parameter::ClientPriority => ParameterValue::ClientPriority(50),
+      Parameter::DriverName => ParameterValue::DriverName(String::new()),
+      Parameter::DriverCode => ParameterValue::DriverCode(String::new()),
+      Parameter::DriverVersion => ParameterValue::DriverVersion(String::new()),
+      Parameter::DeviceModel => ParameterValue::DeviceModel(String::new()),
+      Parameter::DeviceCellSize => ParameterValue::DeviceCellSize(8),
+      Parameter::DisplaySize => ParameterValue::DisplaySize { columns: 0, lines: 0 },
+      Parameter::DeviceIdentifier => ParameterValue::DeviceIdentifier(String::new()),
+      Parameter::DeviceSpeed => ParameterValue::DeviceSpeed(0),
+      Parameter::DeviceOnline => ParameterValue::DeviceOnline(false),
+      Parameter::RetainDots => ParameterValue::RetainDots(false),
+      Parameter::ComputerBrailleCellSize => ParameterValue::ComputerBrailleCellSize(8),
+      Parameter::LiteraryBraille => ParameterValue::LiteraryBraille(false),
+      Parameter::CursorDots => ParameterValue::CursorDots(0xc0),
+      Parameter::CursorBlinkPeriod => ParameterValue::CursorBlinkPeriod(0),
+      Parameter::CursorBlinkPercentage => ParameterValue::CursorBlinkPercentage(0),
+      Parameter::RenderedCells => ParameterValue::RenderedCells(Vec::new()),
+      Parameter::SkipIdenticalLines => ParameterValue::SkipIdenticalLines(false),
+      Parameter::AudibleAlerts => ParameterValue::AudibleAlerts(false),
+      Parameter::ClipboardContent => ParameterValue::ClipboardContent(String::new()),
+      Parameter::BoundCommandKeycodes => ParameterValue::BoundCommandKeycodes(Vec::new()),
+      Parameter::CommandKeycodeName => ParameterValue::CommandKeycodeName(String::new()),
+      Parameter::CommandKeycodeSummary => ParameterValue::CommandKeycodeSummary(String::new()),
+      Parameter::DefinedDriverKeycodes => ParameterValue::DefinedDriverKeycodes(Vec::new()),
+      Parameter::DriverKeycodeName => ParameterValue::DriverKeycodeName(String::new()),
+      Parameter::DriverKeycodeSummary => ParameterValue::DriverKeycodeSummary(String::new()),
+      Parameter::ComputerBrailleRowsMask => ParameterValue::ComputerBrailleRowsMask(Vec::new()),
+      Parameter::ComputerBrailleRowCells => ParameterValue::ComputerBrailleRowCells(Vec::new()),
+      Parameter::ComputerBrailleTable => ParameterValue::ComputerBrailleTable(String::new()),
+      Parameter::LiteraryBrailleTable => ParameterValue::LiteraryBrailleTable(String::new()),
+      Parameter::MessageLocale => ParameterValue::MessageLocale(String::new()),
+    }
+  }
+}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParameterError {
+  UnknownParameter(u32),
+  InvalidValue(Parameter),
+}
+impl fmt::Display for ParameterError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ParameterError::UnknownParameter(parameter) => write!(f, "unknown parameter: {parameter}"),
+      ParameterError::InvalidValue(parameter) => write!(f, "invalid value for parameter {parameter:?}"),
+    }
+  }
+}
+impl std::error::Error for ParameterError {}
+impl TryFrom<u32> for Parameter {
+  type Error = ParameterError;
+  fn try_from(value: u32) -> Result<Self, Self::Error> {
+    Parameter::from_u32(value).ok_or(ParameterError::UnknownParameter(value))
+  }
+}
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParameterValue {
+  ServerVersion(u32),
+  ClientPriority(u32),
+  DriverName(String),
+  DriverCode(String),
+  DriverVersion(String),
+  DeviceModel(String),
+  DeviceCellSize(u8),
+  DisplaySize { columns: u32, lines: u32 },
+  DeviceIdentifier(String),
+  DeviceSpeed(u32),
+  DeviceOnline(bool),
+  RetainDots(bool),
+  ComputerBrailleCellSize(u8),
+  LiteraryBraille(bool),
+  CursorDots(u8),
+  CursorBlinkPeriod(u32),
+  CursorBlinkPercentage(u8),
+  RenderedCells(Vec<u8>),
+  SkipIdenticalLines(bool),
+  AudibleAlerts(bool),
+  ClipboardContent(String),
+  BoundCommandKeycodes(Vec<u64>),
+  CommandKeycodeName(String),
+  CommandKeycodeSummary(String),
+  DefinedDriverKeycodes(Vec<u64>),
+  DriverKeycodeName(String),
+  DriverKeycodeSummary(String),
+  ComputerBrailleRowsMask(Vec<u8>),
+  ComputerBrailleRowCells(Vec<u8>),
+  ComputerBrailleTable(String),
+  LiteraryBrailleTable(String),
+  MessageLocale(String),
+}
+fn decode_u8(parameter: Parameter, value: &[u8]) -> Result<u8, ParameterError> {
+  match value {
+    [byte] => Ok(*byte),
+    _ => Err(ParameterError::InvalidValue(parameter)),
+  }
+}
+fn decode_bool(parameter: Parameter, value: &[u8]) -> Result<bool, ParameterError> {
+  match value {
+    [0] => Ok(false),
+    [1] => Ok(true),
+    _ => Err(ParameterError::InvalidValue(parameter)),
+  }
+}
+fn decode_u32(parameter: Parameter, value: &[u8]) -> Result<u32, ParameterError> {
+  Ok(u32::from_be_bytes(value.try_into().map_err(|_| ParameterError::InvalidValue(parameter))?))
+}
+fn decode_u64_list(parameter: Parameter, value: &[u8]) -> Result<Vec<u64>, ParameterError> {
+  if !value.len().is_multiple_of(8) {
+    return Err(ParameterError::InvalidValue(parameter));
+  }
+  Ok(value.chunks_exact(8).map(|chunk| u64::from_be_bytes(chunk.try_into().unwrap())).collect())
+}
+fn decode_string(parameter: Parameter, value: &[u8]) -> Result<String, ParameterError> {
+  String::from_utf8(value.to_vec()).map_err(|_| ParameterError::InvalidValue(parameter))
+}
+impl ParameterValue {
+  pub fn decode(parameter: Parameter, value: &[u8]) -> Result<Self, ParameterError> {
+    Ok(match parameter {
+      Parameter::ServerVersion => ParameterValue::ServerVersion(decode_u32(parameter, value)?),
+      Parameter::ClientPriority => ParameterValue::ClientPriority(decode_u32(parameter, value)?),
+      Parameter::DriverName => ParameterValue::DriverName(decode_string(parameter, value)?),
+      Parameter::DriverCode => ParameterValue::DriverCode(decode_string(parameter, value)?),
+      Parameter::DriverVersion => ParameterValue::DriverVersion(decode_string(parameter, value)?),
+      Parameter::DeviceModel => ParameterValue::DeviceModel(decode_string(parameter, value)?),
+      Parameter::DeviceCellSize => ParameterValue::DeviceCellSize(decode_u8(parameter, value)?),
+      Parameter::DisplaySize => {
+        if value.len() != 8 {
+          return Err(ParameterError::InvalidValue(parameter));
+        }
+        ParameterValue::DisplaySize { columns: decode_u32(parameter, &value[..4])?, lines: decode_u32(parameter, &value[4..])? }
+      },
+      Parameter::DeviceIdentifier => ParameterValue::DeviceIdentifier(decode_string(parameter, value)?),
+      Parameter::DeviceSpeed => ParameterValue::DeviceSpeed(decode_u32(parameter, value)?),
+      Parameter::DeviceOnline => ParameterValue::DeviceOnline(decode_bool(parameter, value)?),
+      Parameter::RetainDots => ParameterValue::RetainDots(decode_bool(parameter, value)?),
+      Parameter::ComputerBrailleCellSize => ParameterValue::ComputerBrailleCellSize(decode_u8(parameter, value)?),
+      Parameter::LiteraryBraille => ParameterValue::LiteraryBraille(decode_bool(parameter, value)?),
+      Parameter::CursorDots => ParameterValue::CursorDots(decode_u8(parameter, value)?),
+      Parameter::CursorBlinkPeriod => ParameterValue::CursorBlinkPeriod(decode_u32(parameter, value)?),
+      Parameter::CursorBlinkPercentage => ParameterValue::CursorBlinkPercentage(decode_u8(parameter, value)?),
+      Parameter::RenderedCells => ParameterValue::RenderedCells(value.to_vec()),
+      Parameter::SkipIdenticalLines => ParameterValue::SkipIdenticalLines(decode_bool(parameter, value)?),
+      Parameter::AudibleAlerts => ParameterValue::AudibleAlerts(decode_bool(parameter, value)?),
+      Parameter::ClipboardContent => ParameterValue::ClipboardContent(decode_string(parameter, value)?),
+      Parameter::BoundCommandKeycodes => ParameterValue::BoundCommandKeycodes(decode_u64_list(parameter, value)?),
+      Parameter::CommandKeycodeName => ParameterValue::CommandKeycodeName(decode_string(parameter, value)?),
+      Parameter::CommandKeycodeSummary => ParameterValue::CommandKeycodeSummary(decode_string(parameter, value)?),
+      Parameter::DefinedDriverKeycodes => ParameterValue::DefinedDriverKeycodes(decode_u64_list(parameter, value)?),
+      Parameter::DriverKeycodeName => ParameterValue::DriverKeycodeName(decode_string(parameter, value)?),
+      Parameter::DriverKeycodeSummary => ParameterValue::DriverKeycodeSummary(decode_string(parameter, value)?),
+      Parameter::ComputerBrailleRowsMask => ParameterValue::ComputerBrailleRowsMask(value.to_vec()),
+      Parameter::ComputerBrailleRowCells => ParameterValue::ComputerBrailleRowCells(value.to_vec()),
+      Parameter::ComputerBrailleTable => ParameterValue::ComputerBrailleTable(decode_string(parameter, value)?),
+      Parameter::LiteraryBrailleTable => ParameterValue::LiteraryBrailleTable(decode_string(parameter, value)?),
+      Parameter::MessageLocale => ParameterValue::MessageLocale(decode_string(parameter, value)?),
+    })
+  }
+  #[must_use]
+  pub fn encode(&self) -> Vec<u8> {
+    match self {
+      ParameterValue::ServerVersion(value)
+      | ParameterValue::ClientPriority(value)
+      | ParameterValue::DeviceSpeed(value)
+      | ParameterValue::CursorBlinkPeriod(value) => value.to_be_bytes().to_vec(),
+      ParameterValue::DeviceCellSize(value)
+      | ParameterValue::ComputerBrailleCellSize(value)
+      | ParameterValue::CursorDots(value)
+      | ParameterValue::CursorBlinkPercentage(value) => vec![*value],
+      ParameterValue::DeviceOnline(value)
+      | ParameterValue::RetainDots(value)
+      | ParameterValue::LiteraryBraille(value)
+      | ParameterValue::SkipIdenticalLines(value)
+      | ParameterValue::AudibleAlerts(value) => vec![u8::from(*value)],
+      ParameterValue::DisplaySize { columns, lines } => [columns.to_be_bytes(), lines.to_be_bytes()].concat(),
+      ParameterValue::DriverName(value)
+      | ParameterValue::DriverCode(value)
+      | ParameterValue::DriverVersion(value)
+      | ParameterValue::DeviceModel(value)
+      | ParameterValue::DeviceIdentifier(value)
+      | ParameterValue::ClipboardContent(value)
+      | ParameterValue::CommandKeycodeName(value)
+      | ParameterValue::CommandKeycodeSummary(value)
+      | ParameterValue::DriverKeycodeName(value)
+      | ParameterValue::DriverKeycodeSummary(value)
+      | ParameterValue::ComputerBrailleTable(value)
+      | ParameterValue::LiteraryBrailleTable(value)
+      | ParameterValue::MessageLocale(value) => value.as_bytes().to_vec(),
+      ParameterValue::RenderedCells(value)
+      | ParameterValue::ComputerBrailleRowsMask(value)
+      | ParameterValue::ComputerBrailleRowCells(value) => value.clone(),
+      ParameterValue::BoundCommandKeycodes(value)
+      | ParameterValue::DefinedDriverKeycodes(value) => value.iter().flat_map(|keycode| keycode.to_be_bytes()).collect(),
+    }
+  }
+  #[must_use]
+  pub const fn parameter(&self) -> Parameter {
+    match self {
+      ParameterValue::ServerVersion(_) => Parameter::ServerVersion,
+      ParameterValue::ClientPriority(_) => Parameter::ClientPriority,
+      ParameterValue::DriverName(_) => Parameter::DriverName,
+      ParameterValue::DriverCode(_) => Parameter::DriverCode,
+      ParameterValue::DriverVersion(_) => Parameter::DriverVersion,
+      ParameterValue::DeviceModel(_) => Parameter::DeviceModel,
+      ParameterValue::DeviceCellSize(_) => Parameter::DeviceCellSize,
+      ParameterValue::DisplaySize { columns: _, lines: _ } => Parameter::DisplaySize,
+      ParameterValue::DeviceIdentifier(_) => Parameter::DeviceIdentifier,
+      ParameterValue::DeviceSpeed(_) => Parameter::DeviceSpeed,
+      ParameterValue::DeviceOnline(_) => Parameter::DeviceOnline,
+      ParameterValue::RetainDots(_) => Parameter::RetainDots,
+      ParameterValue::ComputerBrailleCellSize(_) => Parameter::ComputerBrailleCellSize,
+      ParameterValue::LiteraryBraille(_) => Parameter::LiteraryBraille,
+      ParameterValue::CursorDots(_) => Parameter::CursorDots,
+      ParameterValue::CursorBlinkPeriod(_) => Parameter::CursorBlinkPeriod,
+      ParameterValue::CursorBlinkPercentage(_) => Parameter::CursorBlinkPercentage,
+      ParameterValue::RenderedCells(_) => Parameter::RenderedCells,
+      ParameterValue::SkipIdenticalLines(_) => Parameter::SkipIdenticalLines,
+      ParameterValue::AudibleAlerts(_) => Parameter::AudibleAlerts,
+      ParameterValue::ClipboardContent(_) => Parameter::ClipboardContent,
+      ParameterValue::BoundCommandKeycodes(_) => Parameter::BoundCommandKeycodes,
+      ParameterValue::CommandKeycodeName(_) => Parameter::CommandKeycodeName,
+      ParameterValue::CommandKeycodeSummary(_) => Parameter::CommandKeycodeSummary,
+      ParameterValue::DefinedDriverKeycodes(_) => Parameter::DefinedDriverKeycodes,
+      ParameterValue::DriverKeycodeName(_) => Parameter::DriverKeycodeName,
+      ParameterValue::DriverKeycodeSummary(_) => Parameter::DriverKeycodeSummary,
+      ParameterValue::ComputerBrailleRowsMask(_) => Parameter::ComputerBrailleRowsMask,
+      ParameterValue::ComputerBrailleRowCells(_) => Parameter::ComputerBrailleRowCells,
+      ParameterValue::ComputerBrailleTable(_) => Parameter::ComputerBrailleTable,
+      ParameterValue::LiteraryBrailleTable(_) => Parameter::LiteraryBrailleTable,
+      ParameterValue::MessageLocale(_) => Parameter::MessageLocale,
+    }
+  }
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const IDS: [(Parameter, u32); 32] = [
+    (Parameter::ServerVersion, 0),
+    (Parameter::ClientPriority, 1),
+    (Parameter::DriverName, 2),
+    (Parameter::DriverCode, 3),
+    (Parameter::DriverVersion, 4),
+    (Parameter::DeviceModel, 5),
+    (Parameter::DeviceCellSize, 31),
+    (Parameter::DisplaySize, 6),
+    (Parameter::DeviceIdentifier, 7),
+    (Parameter::DeviceSpeed, 8),
+    (Parameter::DeviceOnline, 9),
+    (Parameter::RetainDots, 10),
+    (Parameter::ComputerBrailleCellSize, 11),
+    (Parameter::LiteraryBraille, 12),
+    (Parameter::CursorDots, 13),
+    (Parameter::CursorBlinkPeriod, 14),
+    (Parameter::CursorBlinkPercentage, 15),
+    (Parameter::RenderedCells, 16),
+    (Parameter::SkipIdenticalLines, 17),
+    (Parameter::AudibleAlerts, 18),
+    (Parameter::ClipboardContent, 19),
+    (Parameter::BoundCommandKeycodes, 20),
+    (Parameter::CommandKeycodeName, 21),
+    (Parameter::CommandKeycodeSummary, 22),
+    (Parameter::DefinedDriverKeycodes, 23),
+    (Parameter::DriverKeycodeName, 24),
+    (Parameter::DriverKeycodeSummary, 25),
+    (Parameter::ComputerBrailleRowsMask, 26),
+    (Parameter::ComputerBrailleRowCells, 27),
+    (Parameter::ComputerBrailleTable, 28),
+    (Parameter::LiteraryBrailleTable, 29),
+    (Parameter::MessageLocale, 30),
+  ];
+  #[test]
+  fn wire_ids() {
+    for (parameter, id) in IDS {
+      assert_eq!(parameter.into_u32(), id);
+      assert_eq!(Parameter::from_u32(id), Some(parameter));
+    }
+    assert_eq!(Parameter::from_u32(32), None);
+    assert_eq!(Parameter::try_from(u32::MAX), Err(ParameterError::UnknownParameter(u32::MAX)));
+  }
+  #[test]
+  fn default_values_round_trip() {
+    for (parameter, _) in IDS {
+      let value = parameter.default_value();
+      assert_eq!(value.parameter(), parameter);
+      assert_eq!(ParameterValue::decode(parameter, &value.encode()), Ok(value));
+    }
+  }
+  #[test]
+  fn values_round_trip() {
+    let values = [
+      ParameterValue::ServerVersion(8),
+      ParameterValue::DeviceCellSize(6),
+      ParameterValue::DisplaySize { columns: 40, lines: 2 },
+      ParameterValue::DeviceOnline(true),
+      ParameterValue::CursorBlinkPeriod(500),
+      ParameterValue::DriverName("Virtual".to_owned()),
+      ParameterValue::BoundCommandKeycodes(vec![1, u64::MAX]),
+      ParameterValue::RenderedCells(vec![0, 0xff]),
+    ];
+    for value in values {
+      assert_eq!(ParameterValue::decode(value.parameter(), &value.encode()), Ok(value));
+    }
+    assert_eq!(ParameterValue::DisplaySize { columns: 40, lines: 2 }.encode(), [0, 0, 0, 40, 0, 0, 0, 2]);
+  }
+  #[test]
+  fn invalid_values() {
+    assert_eq!(ParameterValue::decode(Parameter::DisplaySize, &[0; 4]), Err(ParameterError::InvalidValue(Parameter::DisplaySize)));
+    assert_eq!(ParameterValue::decode(Parameter::DeviceOnline, &[2]), Err(ParameterError::InvalidValue(Parameter::DeviceOnline)));
+    assert_eq!(ParameterValue::decode(Parameter::BoundCommandKeycodes, &[0; 7]), Err(ParameterError::InvalidValue(Parameter::BoundCommandKeycodes)));
+    assert_eq!(ParameterValue::decode(Parameter::DriverName, &[0xff]), Err(ParameterError::InvalidValue(Parameter::DriverName)));
+  }
+}